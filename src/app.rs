@@ -1,13 +1,35 @@
 //! Main application logic and UI for the fractal visualizer.
 //! Handles user interaction, rendering, and state management.
 
-use crate::types::{ViewRect, Palette, FractalType, FavoriteSetting, PALETTE_NAMES};
+use crate::types::{ViewRect, Palette, Gradient, FractalType, JuliaMode, InverseParams, FavoriteSetting, NoiseKind, NoiseParams, ApollonianParams, KleinianParams, Mobius, Formula, HybridStep, HybridParams, ColoringParams, RenderSettings, ColorAlgorithm, NamedGradient, PaletteFormat, InteriorMode, TrapShape, TrapParams, AverageParams, DistanceMode, LightingParams, ColorLayer, ExtractMethod, StopOrder, TransferFunction, TransferParams, ExportFormat, GeneratorParams, TextureImage, TextureSource, RepeatMode, PALETTE_NAMES, FORMULA_NAMES, INTERIOR_MODE_NAMES, COLOR_ALGORITHM_NAMES, TRANSFER_FUNCTION_NAMES, BLEND_MODE_NAMES, EXPORT_FORMAT_NAMES, HARMONY_RULE_NAMES};
 // palette_color is not used directly here
-use crate::fractal::{render_mandelbrot, render_julia};
-use crate::save::{save_fractal_serialized, export_favorite, import_favorite};
+use crate::palette::gradient_color;
+use crate::colorspace::to_bytes;
+use crate::fractal::{render_fractal, compute_field, OrbitNeeds};
+use crate::coloring::{Sample, build_histogram, colorize, to_rgba8};
+use crate::extract::extract_gradient;
+use crate::generator::generate_gradient;
+use crate::texture::load_texture;
+use crate::save::{save_fractal_serialized, save_heightmap, export_favorite, import_favorite, import_palettes, export_palettes, load_palette_library, save_palette_library};
+use crate::circles::{grandma_recipe, check_apollonian_radii};
+use crate::gradient_editor::gradient_editor;
 use std::sync::{Arc, Mutex};
 use eframe::egui;
 
+/// Size in pixels of the live Julia preview inset.
+const JULIA_PREVIEW_SIZE: [usize; 2] = [200, 150];
+
+/// View used when opening a Julia set from the linked Mandelbrot map.
+const JULIA_VIEW: ViewRect = ViewRect {
+	min_x: -2.0,
+	max_x: 2.0,
+	min_y: -1.5,
+	max_y: 1.5,
+};
+
+/// Shown for the exponential smoothing options on fractals that track no orbits.
+const ORBIT_ONLY_HINT: &str = "Exponential smoothing needs an orbit: Mandelbrot, escape-time Julia or hybrid only";
+
 /// The main application struct, holding all state for the fractal visualizer UI.
 pub struct FractalApp {
 	/// Handle to the current fractal image texture (for display)
@@ -30,35 +52,100 @@ pub struct FractalApp {
 	pub last_palette: Palette,
 	/// Message to display after save/export actions
 	pub save_message: Option<String>,
-	/// Which fractal to render (Mandelbrot or Julia)
+	/// Which fractal to render (Mandelbrot, Julia or Noise)
 	pub fractal_type: FractalType,
 	/// Julia set parameter (re, im)
 	pub julia_param: (f64, f64),
+	/// How the Julia set is drawn (escape-time, inverse iteration, or both)
+	pub julia_mode: JuliaMode,
+	/// Inverse iteration (MIIM) settings
+	pub inverse: InverseParams,
+	/// Procedural noise settings (used in Noise mode)
+	pub noise: NoiseParams,
+	/// Apollonian gasket settings (used in Apollonian mode)
+	pub apollonian: ApollonianParams,
+	/// Kleinian group generators (used in Kleinian mode)
+	pub kleinian: KleinianParams,
+	/// Traces (ta, tb) fed to Grandma's recipe to build Kleinian generators
+	pub kleinian_traces: ((f64, f64), (f64, f64)),
+	/// Hybrid formula list (used in Hybrid mode)
+	pub hybrid: HybridParams,
+	/// Linked mode: hovering the Mandelbrot map previews the Julia set for the c under the cursor
+	pub linked_julia: bool,
+	/// Texture of the live Julia preview inset
+	pub julia_preview: Option<egui::TextureHandle>,
+	/// The c value the preview was last rendered for
+	pub julia_preview_c: Option<(f64, f64)>,
 	/// Is a high-res save in progress?
 	pub highres_in_progress: bool,
 	// pub highres_progress: f32, // unused
 	/// Result of the high-res save thread (shared via Arc<Mutex<..>>)
 	pub highres_result: Arc<Mutex<Option<Result<String, String>>>>,
-	/// User-defined palette colors (for gradient)
-	pub user_palette: [(u8, u8, u8); 2],
+	/// User-defined multi-stop gradient
+	pub gradient: Gradient,
+	/// Stop selected in the gradient editor
+	pub selected_stop: Option<usize>,
 	/// Should the import favorite dialog be shown?
 	pub show_import_dialog: bool,
+	/// The user's palette library, listed after the built-in palettes and saved in the config directory
+	pub palette_list: Vec<NamedGradient>,
+	/// Index into `palette_list` of the palette being edited, if any
+	pub selected_custom: Option<usize>,
+	/// Gradient edits to `palette_list` not yet written to the palette library
+	pub palette_library_dirty: bool,
+	/// Should the import palette dialog be shown?
+	pub show_palette_import_dialog: bool,
+	/// File format and bit depth of saved images
+	pub export_format: ExportFormat,
+	/// Settings of the seeded palette generator
+	pub generator: GeneratorParams,
+	/// Should the extract-from-image dialog be shown?
+	pub show_extract_dialog: bool,
+	/// Number of stops to extract from a reference image
+	pub extract_stops: usize,
+	/// Color clustering used for extraction
+	pub extract_method: ExtractMethod,
+	/// Order of the extracted stops
+	pub extract_order: StopOrder,
+	/// Should the texture image dialog be shown?
+	pub show_texture_dialog: bool,
+	/// Image loaded from `coloring.texture.path`, shared with high-res renders
+	pub texture_image: Option<Arc<TextureImage>>,
+	/// How iteration data is mapped onto the palette
+	pub coloring: ColoringParams,
+	/// Samples of the last render, recolored without iterating again
+	pub field: Vec<Sample>,
+	/// Orbit data tracked in `field`
+	pub field_needs: OrbitNeeds,
+	/// Is color cycling running?
+	pub cycling: bool,
+	/// Color cycling speed in palette lengths per second
+	pub cycle_speed: f64,
+	/// Cycle colors backwards
+	pub cycle_reverse: bool,
+	/// Orbit trap tracked while iterating
+	pub trap: TrapParams,
+	/// Is the user dragging the orbit trap handle?
+	pub trap_dragging: bool,
+	/// Is the user dragging the orbit trap's size handle?
+	pub trap_resizing: bool,
+	/// Stripe density and blend of the average coloring algorithms
+	pub averages: AverageParams,
 }
 
 impl FractalApp {
-	/// Helper: List all favorite JSON files in the 0_fractals/ directory.
-	fn list_favorite_files() -> Vec<String> {
+	/// Helper: List all files in the 0_fractals/ directory with one of the given extensions.
+	fn list_output_files(extensions: &[&str]) -> Vec<String> {
 		let dir = "0_fractals";
 		let mut files = Vec::new();
 		if let Ok(entries) = std::fs::read_dir(dir) {
 			for entry in entries.flatten() {
 				let path = entry.path();
-				if let Some(ext) = path.extension() {
-					if ext == "json" {
-						if let Some(path_str) = path.to_str() {
-							files.push(path_str.to_string());
-						}
-					}
+				if let Some(ext) = path.extension().and_then(|e| e.to_str())
+					&& extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+					&& let Some(path_str) = path.to_str()
+				{
+					files.push(path_str.to_string());
 				}
 			}
 			files.sort();
@@ -73,7 +160,7 @@ impl FractalApp {
 			.collapsible(false)
 			.open(&mut open)
 			.show(ctx, |ui| {
-				let files = Self::list_favorite_files();
+				let files = Self::list_output_files(&["json"]);
 				if files.is_empty() {
 					ui.label("No favorite JSON files found in 0_fractals/");
 				} else {
@@ -92,6 +179,388 @@ impl FractalApp {
 				}
 			});
 	}
+
+	/// Show a popup dialog to select a palette file to import into the palette list.
+	pub fn show_import_palette_dialog(&mut self, ctx: &egui::Context) {
+		egui::Window::new("Import Palette")
+			.collapsible(false)
+			.show(ctx, |ui| {
+				let files = Self::list_output_files(&["map", "ggr", "ugr", "gradient"]);
+				if files.is_empty() {
+					ui.label("No .map, .ggr, .ugr or .gradient files found in 0_fractals/");
+				} else {
+					for file in files {
+						if ui.button(&file).clicked() {
+							match import_palettes(&file) {
+								Ok(palettes) => {
+									self.save_message = Some(format!("Imported {} palette(s) from {}", palettes.len(), file));
+									let first = self.palette_list.len();
+									self.palette_list.extend(palettes);
+									self.store_palette_library();
+									self.select_custom_palette(first, ctx);
+								}
+								Err(e) => self.save_message = Some(format!("Failed to import: {e}")),
+							}
+							self.show_palette_import_dialog = false;
+						}
+					}
+				}
+				if ui.button("Cancel").clicked() {
+					self.show_palette_import_dialog = false;
+				}
+			});
+	}
+
+	/// Show a popup dialog to pick a reference image and extract a gradient from its colors.
+	pub fn show_extract_palette_dialog(&mut self, ctx: &egui::Context) {
+		egui::Window::new("Extract Palette from Image")
+			.collapsible(false)
+			.show(ctx, |ui| {
+				ui.horizontal(|ui| {
+					ui.label("Stops:");
+					ui.add(egui::DragValue::new(&mut self.extract_stops).clamp_range(2..=32));
+					ui.selectable_value(&mut self.extract_method, ExtractMethod::KMeans, "K-means");
+					ui.selectable_value(&mut self.extract_method, ExtractMethod::MedianCut, "Median cut");
+					ui.label("Order by:");
+					ui.selectable_value(&mut self.extract_order, StopOrder::Luminance, "Luminance");
+					ui.selectable_value(&mut self.extract_order, StopOrder::Hue, "Hue");
+				});
+				let files = Self::list_output_files(&["png", "jpg", "jpeg"]);
+				if files.is_empty() {
+					ui.label("No .png, .jpg or .jpeg files found in 0_fractals/");
+				} else {
+					for file in files {
+						if ui.button(&file).clicked() {
+							match extract_gradient(&file, self.extract_stops, self.extract_method, self.extract_order) {
+								Ok(gradient) => {
+									let name = std::path::Path::new(&file)
+										.file_stem()
+										.and_then(|s| s.to_str())
+										.unwrap_or("Extracted")
+										.to_string();
+									self.save_message = Some(format!("Extracted {} colors from {}", gradient.stops.len(), file));
+									self.palette_list.push(NamedGradient { name, gradient });
+									self.store_palette_library();
+									self.select_custom_palette(self.palette_list.len() - 1, ctx);
+								}
+								Err(e) => self.save_message = Some(format!("Failed to extract: {e}")),
+							}
+							self.show_extract_dialog = false;
+						}
+					}
+				}
+				if ui.button("Cancel").clicked() {
+					self.show_extract_dialog = false;
+				}
+			});
+	}
+
+	/// Show the dialog to pick the texture image from the 0_fractals directory.
+	pub fn show_texture_image_dialog(&mut self, ctx: &egui::Context) {
+		egui::Window::new("Choose Texture Image")
+			.collapsible(false)
+			.show(ctx, |ui| {
+				let files = Self::list_output_files(&["png", "jpg", "jpeg"]);
+				if files.is_empty() {
+					ui.label("No .png, .jpg or .jpeg files found in 0_fractals/");
+				} else {
+					for file in files {
+						if ui.button(&file).clicked() {
+							self.coloring.texture.path = file;
+							self.coloring.texture.enabled = true;
+							self.load_texture_image();
+							self.recolor(ctx);
+							self.show_texture_dialog = false;
+						}
+					}
+				}
+				if ui.button("Cancel").clicked() {
+					self.show_texture_dialog = false;
+				}
+			});
+	}
+
+	/// Load the image at `coloring.texture.path`, reporting failures in the status line.
+	fn load_texture_image(&mut self) {
+		self.texture_image = None;
+		let path = &self.coloring.texture.path;
+		if path.is_empty() {
+			return;
+		}
+		match load_texture(path) {
+			Ok(image) => self.texture_image = Some(Arc::new(image)),
+			Err(e) => self.save_message = Some(format!("Failed to load texture: {e}")),
+		}
+	}
+
+	/// Write `palette_list` to the user's palette library, reporting failures in the status line.
+	fn store_palette_library(&mut self) {
+		if let Err(e) = save_palette_library(&self.palette_list) {
+			self.save_message = Some(format!("Failed to save palette library: {e}"));
+		}
+	}
+
+	/// Library row: create, rename, duplicate, delete and reorder palettes in `palette_list`.
+	fn palette_library_row(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+		ui.horizontal(|ui| {
+			ui.label("Library:");
+			if ui.button("New").clicked() {
+				let name = format!("Palette {}", self.palette_list.len() + 1);
+				self.palette_list.push(NamedGradient { name, gradient: self.gradient.clone() });
+				self.store_palette_library();
+				self.select_custom_palette(self.palette_list.len() - 1, ctx);
+			}
+			let Some(i) = self.selected_custom.filter(|&i| i < self.palette_list.len() && self.palette == Palette::UserDefined) else {
+				return;
+			};
+			let name = ui.add(egui::TextEdit::singleline(&mut self.palette_list[i].name).desired_width(140.0));
+			if name.lost_focus() {
+				self.store_palette_library();
+			}
+			if ui.button("Duplicate").clicked() {
+				let mut copy = self.palette_list[i].clone();
+				copy.name = format!("{} copy", copy.name);
+				self.palette_list.insert(i + 1, copy);
+				self.store_palette_library();
+				self.select_custom_palette(i + 1, ctx);
+			}
+			if ui.button("Delete").clicked() {
+				self.palette_list.remove(i);
+				self.selected_custom = None;
+				self.store_palette_library();
+			}
+			if ui.add_enabled(i > 0, egui::Button::new("Move up")).clicked() {
+				self.palette_list.swap(i, i - 1);
+				self.selected_custom = Some(i - 1);
+				self.store_palette_library();
+			}
+			if ui.add_enabled(i + 1 < self.palette_list.len(), egui::Button::new("Move down")).clicked() {
+				self.palette_list.swap(i, i + 1);
+				self.selected_custom = Some(i + 1);
+				self.store_palette_library();
+			}
+		});
+	}
+
+	/// Generator row: harmony rule, seed and stop count, a preview strip of the result, and
+	/// buttons to use it as the user gradient or save it to the palette list.
+	fn palette_generator_row(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+		ui.horizontal(|ui| {
+			ui.label("Generate:");
+			let generator = &mut self.generator;
+			egui::ComboBox::from_id_source("harmony_rule")
+				.selected_text(HARMONY_RULE_NAMES.iter().find(|(_, r)| *r == generator.rule).map(|(n, _)| *n).unwrap_or("Unknown"))
+				.show_ui(ui, |cb| {
+					for (name, rule) in HARMONY_RULE_NAMES {
+						cb.selectable_value(&mut generator.rule, *rule, *name);
+					}
+				});
+			ui.label("Seed:");
+			ui.add(egui::DragValue::new(&mut generator.seed));
+			if ui.button("Random").clicked() {
+				let nanos = std::time::SystemTime::now()
+					.duration_since(std::time::UNIX_EPOCH)
+					.map(|d| d.as_nanos() as u64)
+					.unwrap_or(0);
+				// Keep seeds short enough to note down
+				generator.seed = nanos % 1_000_000;
+			}
+			ui.label("Stops:");
+			ui.add(egui::DragValue::new(&mut generator.stops).clamp_range(2..=16));
+
+			let gradient = generate_gradient(generator);
+			let (strip, _) = ui.allocate_exact_size(egui::vec2(160.0, 20.0), egui::Sense::hover());
+			let slices = strip.width() as usize / 2;
+			for s in 0..slices {
+				let [r, g, b] = to_bytes(gradient_color(&gradient, s as f64 / (slices - 1) as f64));
+				let x0 = strip.min.x + s as f32 * 2.0;
+				let slice = egui::Rect::from_min_max(egui::pos2(x0, strip.min.y), egui::pos2(x0 + 2.0, strip.max.y));
+				ui.painter().rect_filled(slice, 0.0, egui::Color32::from_rgb(r, g, b));
+			}
+			ui.painter().rect_stroke(strip, 0.0, (1.0, egui::Color32::GRAY));
+
+			if ui.button("Use").clicked() {
+				self.gradient = gradient.clone();
+				self.palette = Palette::UserDefined;
+				self.last_palette = Palette::UserDefined;
+				self.selected_custom = None;
+				self.selected_stop = None;
+				self.recolor(ctx);
+			}
+			if ui.button("Save to list").clicked() {
+				let rule = HARMONY_RULE_NAMES.iter().find(|(_, r)| *r == self.generator.rule).map(|(n, _)| *n).unwrap_or("Generated");
+				let name = format!("{} {}", rule, self.generator.seed);
+				self.palette_list.push(NamedGradient { name, gradient });
+				self.store_palette_library();
+				self.select_custom_palette(self.palette_list.len() - 1, ctx);
+			}
+		});
+	}
+
+	/// Switch to a palette from `palette_list`, editing a copy of its gradient.
+	fn select_custom_palette(&mut self, index: usize, ctx: &egui::Context) {
+		if let Some(named) = self.palette_list.get(index) {
+			self.gradient = named.gradient.clone();
+			self.palette = Palette::UserDefined;
+			self.last_palette = Palette::UserDefined;
+			self.selected_custom = Some(index);
+			self.selected_stop = None;
+			self.recolor(ctx);
+		}
+	}
+
+	/// Export the current user gradient to a palette file in `0_fractals/`.
+	fn export_palette(&self, format: PaletteFormat) -> Result<String, String> {
+		let name = self
+			.selected_custom
+			.and_then(|i| self.palette_list.get(i))
+			.map(|p| p.name.clone())
+			.unwrap_or_else(|| "Custom".to_string());
+		export_palettes(&[NamedGradient { name, gradient: self.gradient.clone() }], format)
+	}
+
+	/// Paint a gray checkerboard behind the image so transparent areas are visible.
+	fn checkerboard(ui: &egui::Ui, rect: egui::Rect) {
+		const CELL: f32 = 10.0;
+		let painter = ui.painter_at(rect);
+		painter.rect_filled(rect, 0.0, egui::Color32::from_gray(200));
+		let (cols, rows) = ((rect.width() / CELL).ceil() as usize, (rect.height() / CELL).ceil() as usize);
+		for row in 0..rows {
+			for col in (row % 2..cols).step_by(2) {
+				let min = rect.min + egui::vec2(col as f32 * CELL, row as f32 * CELL);
+				painter.rect_filled(egui::Rect::from_min_size(min, egui::vec2(CELL, CELL)), 0.0, egui::Color32::from_gray(150));
+			}
+		}
+	}
+
+	/// Does the current fractal iterate an orbit per pixel? Only those fill in the
+	/// exponential smoothing sums; the other renderers leave them at zero.
+	fn tracks_orbits(&self) -> bool {
+		match self.fractal_type {
+			FractalType::Mandelbrot | FractalType::Hybrid => true,
+			FractalType::Julia => self.julia_mode != JuliaMode::Inverse,
+			FractalType::Noise | FractalType::Apollonian | FractalType::Kleinian => false,
+		}
+	}
+
+	/// Dropdown entry that sets `value` to `option`, disabled with an explanation when the
+	/// option needs an orbit and `orbits` is false. Returns true if the value changed.
+	fn orbit_option<T: PartialEq + Copy>(ui: &mut egui::Ui, value: &mut T, option: T, name: &str, needs_orbit: bool, orbits: bool) -> bool {
+		let response = ui
+			.add_enabled(orbits || !needs_orbit, egui::SelectableLabel::new(*value == option, name))
+			.on_disabled_hover_text(ORBIT_ONLY_HINT);
+		if response.clicked() && *value != option {
+			*value = option;
+			return true;
+		}
+		false
+	}
+
+	/// Small dial for the light direction: the angle around the center is the azimuth and
+	/// the distance from the center sets the elevation (center = straight overhead).
+	/// Returns true if the direction changed.
+	fn light_dial(ui: &mut egui::Ui, lighting: &mut LightingParams) -> bool {
+		let (rect, response) = ui.allocate_exact_size(egui::vec2(48.0, 48.0), egui::Sense::click_and_drag());
+		let center = rect.center();
+		let radius = rect.width() / 2.0 - 2.0;
+		let mut changed = false;
+		if let Some(pos) = response.interact_pointer_pos()
+			&& (response.clicked() || response.dragged())
+		{
+			let d = (pos - center) / radius;
+			lighting.azimuth = (d.y as f64).atan2(d.x as f64).to_degrees();
+			lighting.elevation = (d.length().min(1.0) as f64).acos().to_degrees();
+			changed = true;
+		}
+		let painter = ui.painter();
+		painter.circle_stroke(center, radius, (1.0, egui::Color32::GRAY));
+		let (az, el) = (lighting.azimuth.to_radians() as f32, lighting.elevation.to_radians() as f32);
+		let light = center + egui::vec2(az.cos(), az.sin()) * el.cos() * radius;
+		painter.line_segment([center, light], (1.0, egui::Color32::GRAY));
+		painter.circle_filled(light, 4.0, egui::Color32::YELLOW);
+		changed
+	}
+
+	/// Transfer function dropdown, with the exponent for the power curve. Returns true if changed.
+	fn transfer_editor(ui: &mut egui::Ui, id: impl std::hash::Hash, transfer: &mut TransferParams) -> bool {
+		let mut changed = false;
+		egui::ComboBox::from_id_source(id)
+			.selected_text(
+				TRANSFER_FUNCTION_NAMES.iter().find(|(_, f)| *f == transfer.function).map(|(n, _)| *n).unwrap_or("Unknown")
+			)
+			.show_ui(ui, |cb| {
+				for (name, function) in TRANSFER_FUNCTION_NAMES {
+					changed |= cb.selectable_value(&mut transfer.function, *function, *name).changed();
+				}
+			});
+		if transfer.function == TransferFunction::Power {
+			changed |= ui.add(egui::DragValue::new(&mut transfer.exponent).speed(0.01).clamp_range(0.05..=10.0).prefix("^")).changed();
+		}
+		changed
+	}
+
+	/// Edit the four complex coefficients of a Möbius generator. Returns true if any changed.
+	fn mobius_editor(ui: &mut egui::Ui, label: &str, m: &mut Mobius) -> bool {
+		let mut changed = false;
+		ui.label(label);
+		for (name, (re, im)) in ["a", "b", "c", "d"].iter().zip(m.iter_mut()) {
+			ui.label(*name);
+			changed |= ui.add(egui::DragValue::new(re).speed(0.01)).changed();
+			changed |= ui.add(egui::DragValue::new(im).speed(0.01).suffix("i")).changed();
+		}
+		changed
+	}
+
+	/// Show the hybrid formula list editor. Returns true if the list changed.
+	fn hybrid_editor(&mut self, ui: &mut egui::Ui) -> bool {
+		let mut changed = false;
+		ui.horizontal(|ui| {
+			ui.label("Hybrid steps:");
+			changed |= ui.checkbox(&mut self.hybrid.repeat, "Repeat").changed();
+			changed |= ui.checkbox(&mut self.hybrid.julia, "Julia (uses c)").changed();
+			if ui.button("Add Step").clicked() {
+				self.hybrid.steps.push(HybridStep { formula: Formula::Mandelbrot, power: 2.0, iterations: 1 });
+				changed = true;
+			}
+		});
+		let count = self.hybrid.steps.len();
+		let mut move_up = None;
+		let mut remove = None;
+		for (idx, step) in self.hybrid.steps.iter_mut().enumerate() {
+			ui.horizontal(|ui| {
+				ui.label(format!("{}.", idx + 1));
+				egui::ComboBox::from_id_source(("hybrid_formula", idx))
+					.selected_text(
+						FORMULA_NAMES.iter().find(|(_, f)| *f == step.formula).map(|(n, _)| *n).unwrap_or("Unknown")
+					)
+					.show_ui(ui, |cb| {
+						for (name, formula) in FORMULA_NAMES {
+							changed |= cb.selectable_value(&mut step.formula, *formula, *name).changed();
+						}
+					});
+				ui.label("Power:");
+				changed |= ui.add(egui::DragValue::new(&mut step.power).speed(0.01).clamp_range(1.0..=16.0)).changed();
+				ui.label("Iterations:");
+				changed |= ui.add(egui::DragValue::new(&mut step.iterations).clamp_range(1..=255)).changed();
+				if ui.add_enabled(idx > 0, egui::Button::new("Raise")).clicked() {
+					move_up = Some(idx);
+				}
+				if ui.add_enabled(count > 1, egui::Button::new("Remove")).clicked() {
+					remove = Some(idx);
+				}
+			});
+		}
+		if let Some(idx) = move_up {
+			self.hybrid.steps.swap(idx - 1, idx);
+			changed = true;
+		}
+		if let Some(idx) = remove {
+			self.hybrid.steps.remove(idx);
+			changed = true;
+		}
+		changed
+	}
+
 	/// Create a new FractalApp with default view and palette.
 	pub fn new(ctx: &egui::Context) -> Self {
 		let width = 800;
@@ -104,16 +573,8 @@ impl FractalApp {
 			max_y: 1.0,
 		};
 		let palette = Palette::Classic;
-		// Render initial Mandelbrot image
-		let pixels = render_mandelbrot(width, height, view, palette, &[(0, 255, 255), (255, 0, 255)]);
-		let color_image = egui::ColorImage::from_rgb([width, height], &pixels);
-		let texture_handle = Some(ctx.load_texture(
-			"mandelbrot",
-			color_image,
-			egui::TextureOptions::default(),
-		));
-		Self {
-			texture_handle,
+		let mut app = Self {
+			texture_handle: None,
 			width,
 			height,
 			view,
@@ -125,21 +586,100 @@ impl FractalApp {
 			save_message: None,
 			fractal_type: FractalType::Mandelbrot,
 			julia_param: (-0.8, 0.156),
+			julia_mode: JuliaMode::EscapeTime,
+			inverse: InverseParams::default(),
+			noise: NoiseParams::default(),
+			apollonian: ApollonianParams::default(),
+			kleinian: KleinianParams::default(),
+			kleinian_traces: ((2.0, 0.0), (2.0, 0.0)),
+			hybrid: HybridParams::default(),
+			linked_julia: false,
+			julia_preview: None,
+			julia_preview_c: None,
 			highres_in_progress: false,
 			// highres_progress: 0.0, // removed
 			highres_result: Arc::new(Mutex::new(None)),
-			user_palette: [(0, 255, 255), (255, 0, 255)],
+			gradient: Gradient::default(),
+			selected_stop: None,
 			show_import_dialog: false,
+			palette_list: Vec::new(),
+			selected_custom: None,
+			palette_library_dirty: false,
+			show_palette_import_dialog: false,
+			export_format: ExportFormat::default(),
+			generator: GeneratorParams::default(),
+			show_extract_dialog: false,
+			extract_stops: 8,
+			extract_method: ExtractMethod::KMeans,
+			extract_order: StopOrder::Luminance,
+			show_texture_dialog: false,
+			texture_image: None,
+			coloring: ColoringParams::default(),
+			field: Vec::new(),
+			field_needs: OrbitNeeds::default(),
+			cycling: false,
+			cycle_speed: 0.2,
+			cycle_reverse: false,
+			trap: TrapParams::default(),
+			trap_dragging: false,
+			trap_resizing: false,
+			averages: AverageParams::default(),
+		};
+		match load_palette_library() {
+			Ok(palettes) => app.palette_list = palettes,
+			Err(e) => app.save_message = Some(format!("Failed to load palette library: {e}")),
+		}
+		// Render initial Mandelbrot image
+		app.rerender(ctx);
+		app
+	}
+
+	/// Collect the current view, palette and fractal parameters for rendering or saving.
+	pub fn render_settings(&self) -> RenderSettings {
+		RenderSettings {
+			view: self.view,
+			palette: self.palette,
+			gradient: self.gradient.clone(),
+			fractal_type: self.fractal_type,
+			julia_param: self.julia_param,
+			julia_mode: self.julia_mode,
+			inverse: self.inverse,
+			noise: self.noise,
+			apollonian: self.apollonian,
+			kleinian: self.kleinian,
+			hybrid: self.hybrid.clone(),
+			coloring: self.coloring.clone(),
+			trap: self.trap,
+			averages: self.averages,
+			texture: self.texture_image.clone(),
 		}
 	}
 
+	/// Keep the current frame's histogram so later renders use the same color mapping.
+	fn freeze_histogram(&mut self) {
+		self.coloring.frozen_histogram = Some(build_histogram(&self.field, self.coloring.smooth));
+	}
+
 	/// Rerender the fractal image and update the texture.
 	pub fn rerender(&mut self, ctx: &egui::Context) {
-		let pixels = match self.fractal_type {
-			FractalType::Mandelbrot => render_mandelbrot(self.width, self.height, self.view, self.palette, &self.user_palette),
-			FractalType::Julia => render_julia(self.width, self.height, self.view, self.palette, &self.user_palette, self.julia_param),
-		};
-		let color_image = egui::ColorImage::from_rgb([self.width, self.height], &pixels);
+		self.field = compute_field(self.width, self.height, &self.render_settings());
+		self.field_needs = OrbitNeeds::new(&self.coloring);
+		self.recolor(ctx);
+	}
+
+	/// Recolor the cached samples of the last render and update the texture.
+	///
+	/// Rerenders instead if the coloring reads orbit data the cached samples were computed without.
+	pub fn recolor(&mut self, ctx: &egui::Context) {
+		if !self.field_needs.covers(&OrbitNeeds::new(&self.coloring)) {
+			self.rerender(ctx);
+			return;
+		}
+		// Palette or other settings may have changed, so the Julia preview is stale
+		self.julia_preview_c = None;
+		// The float colors are quantized to 8 bits only here, for display
+		let pixels = to_rgba8(&colorize(&self.field, self.width, &self.render_settings()));
+		let color_image = egui::ColorImage::from_rgba_unmultiplied([self.width, self.height], &pixels);
 		self.texture_handle = Some(ctx.load_texture(
 			"mandelbrot",
 			color_image,
@@ -147,13 +687,175 @@ impl FractalApp {
 		));
 	}
 
+	/// Map a screen position inside the image rect to a point in the complex plane.
+	fn to_plane(&self, rect: egui::Rect, pos: egui::Pos2) -> (f64, f64) {
+		let x = self.view.min_x + ((pos.x - rect.min.x) / rect.width()) as f64 * (self.view.max_x - self.view.min_x);
+		let y = self.view.min_y + ((pos.y - rect.min.y) / rect.height()) as f64 * (self.view.max_y - self.view.min_y);
+		(x, y)
+	}
+
+	/// Map a point in the complex plane to a screen position inside the image rect.
+	fn to_screen(&self, rect: egui::Rect, (x, y): (f64, f64)) -> egui::Pos2 {
+		egui::pos2(
+			rect.min.x + ((x - self.view.min_x) / (self.view.max_x - self.view.min_x)) as f32 * rect.width(),
+			rect.min.y + ((y - self.view.min_y) / (self.view.max_y - self.view.min_y)) as f32 * rect.height(),
+		)
+	}
+
+	/// Rerender the Julia preview inset if `c` differs from the last preview.
+	fn update_julia_preview(&mut self, ctx: &egui::Context, c: (f64, f64)) {
+		if self.julia_preview_c == Some(c) {
+			return;
+		}
+		let [w, h] = JULIA_PREVIEW_SIZE;
+		let settings = RenderSettings {
+			view: JULIA_VIEW,
+			fractal_type: FractalType::Julia,
+			julia_param: c,
+			julia_mode: JuliaMode::EscapeTime,
+			// A histogram frozen on the main view does not fit the preview
+			coloring: ColoringParams { frozen_histogram: None, ..self.coloring.clone() },
+			..self.render_settings()
+		};
+		let pixels = to_rgba8(&render_fractal(w, h, &settings));
+		let color_image = egui::ColorImage::from_rgba_unmultiplied([w, h], &pixels);
+		self.julia_preview = Some(ctx.load_texture("julia_preview", color_image, egui::TextureOptions::default()));
+		self.julia_preview_c = Some(c);
+	}
+
+	/// Draw the Julia c marker and, while hovering, the live preview inset on the Mandelbrot map.
+	/// Clicking the map opens the full Julia view for the clicked c.
+	fn linked_julia_overlay(&mut self, ctx: &egui::Context, ui: &egui::Ui, rect: egui::Rect, response: &egui::Response) {
+		let marker = self.to_screen(rect, self.julia_param);
+		if rect.contains(marker) {
+			ui.painter().circle_stroke(marker, 5.0, (2.0, egui::Color32::WHITE));
+			ui.painter().line_segment([marker - egui::vec2(8.0, 0.0), marker + egui::vec2(8.0, 0.0)], (1.0, egui::Color32::WHITE));
+			ui.painter().line_segment([marker - egui::vec2(0.0, 8.0), marker + egui::vec2(0.0, 8.0)], (1.0, egui::Color32::WHITE));
+		}
+
+		if response.clicked()
+			&& let Some(pos) = response.interact_pointer_pos()
+		{
+			self.julia_param = self.to_plane(rect, pos);
+			self.fractal_type = FractalType::Julia;
+			self.view = JULIA_VIEW;
+			self.rerender(ctx);
+			return;
+		}
+
+		if let Some(pos) = response.hover_pos()
+			&& !self.dragging
+		{
+			let c = self.to_plane(rect, pos);
+			self.update_julia_preview(ctx, c);
+			if let Some(texture) = &self.julia_preview {
+				let size = egui::vec2(JULIA_PREVIEW_SIZE[0] as f32, JULIA_PREVIEW_SIZE[1] as f32);
+				let inset = egui::Rect::from_min_size(rect.right_top() + egui::vec2(-size.x - 8.0, 8.0), size);
+				ui.painter().image(
+					texture.id(),
+					inset,
+					egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+					egui::Color32::WHITE,
+				);
+				ui.painter().rect_stroke(inset, 0.0, (1.0, egui::Color32::WHITE));
+				ui.painter().text(
+					inset.left_bottom() + egui::vec2(4.0, -4.0),
+					egui::Align2::LEFT_BOTTOM,
+					format!("c = {:.4} {:+.4}i", c.0, c.1),
+					egui::FontId::monospace(12.0),
+					egui::Color32::WHITE,
+				);
+			}
+		}
+	}
+
+	/// Draw the orbit trap over the image and let the user drag its center and size handles.
+	///
+	/// Returns true while the trap is being dragged, so the drag does not zoom.
+	fn trap_overlay(&mut self, ctx: &egui::Context, ui: &egui::Ui, rect: egui::Rect, response: &egui::Response) -> bool {
+		let painter = ui.painter_at(rect);
+		let stroke = (1.5, egui::Color32::WHITE);
+		let center = self.to_screen(rect, self.trap.center);
+		let scale = rect.width() / (self.view.max_x - self.view.min_x) as f32;
+		let (sin, cos) = (self.trap.angle.to_radians() as f32).sin_cos();
+		let along = egui::vec2(cos, sin) * rect.width().max(rect.height()) * 2.0;
+		let across = egui::vec2(-sin, cos) * rect.width().max(rect.height()) * 2.0;
+		match self.trap.shape {
+			TrapShape::Point => {}
+			TrapShape::Line => {
+				painter.line_segment([center - along, center + along], stroke);
+			}
+			TrapShape::Cross | TrapShape::Grid => {
+				painter.line_segment([center - along, center + along], stroke);
+				painter.line_segment([center - across, center + across], stroke);
+			}
+			TrapShape::Circle => {
+				painter.circle_stroke(center, self.trap.size as f32 * scale, stroke);
+			}
+			TrapShape::Ring => {
+				let half = self.trap.width as f32 / 2.0;
+				painter.circle_stroke(center, (self.trap.size as f32 - half).max(0.0) * scale, stroke);
+				painter.circle_stroke(center, (self.trap.size as f32 + half) * scale, stroke);
+			}
+		}
+		painter.circle_filled(center, 5.0, egui::Color32::WHITE);
+		painter.circle_stroke(center, 5.0, (1.0, egui::Color32::BLACK));
+		// The size handle sits one trap size from the center, along the trap's rotation
+		let handle = center + egui::vec2(cos, sin) * self.trap.size as f32 * scale;
+		painter.line_segment([center, handle], (1.0, egui::Color32::WHITE));
+		painter.circle_filled(handle, 4.0, egui::Color32::BLACK);
+		painter.circle_stroke(handle, 4.0, (1.5, egui::Color32::WHITE));
+
+		if response.drag_started()
+			&& let Some(pos) = response.interact_pointer_pos()
+		{
+			// The size handle wins where the two overlap, so a tiny trap can still be enlarged
+			if pos.distance(handle) < 10.0 {
+				self.trap_resizing = true;
+			} else if pos.distance(center) < 10.0 {
+				self.trap_dragging = true;
+			}
+		}
+		if !self.trap_dragging && !self.trap_resizing {
+			return false;
+		}
+		if let Some(pos) = response.interact_pointer_pos() {
+			let before = self.trap;
+			if self.trap_resizing {
+				self.trap.size = ((pos.distance(center) / scale) as f64).clamp(0.001, 10.0);
+			} else {
+				self.trap.center = self.to_plane(rect, pos);
+			}
+			// The trap is tracked while iterating, so the image is rerendered as it moves
+			if self.trap != before {
+				self.rerender(ctx);
+			}
+		}
+		if response.drag_stopped() {
+			self.trap_dragging = false;
+			self.trap_resizing = false;
+		}
+		true
+	}
+
 	/// Export the current view and settings as a favorite (JSON file).
 	pub fn export_favorite(&self) -> Result<String, String> {
 		let fav = FavoriteSetting {
 			view: self.view,
 			palette: self.palette,
+			gradient: self.gradient.clone(),
 			fractal_type: self.fractal_type,
 			julia_param: self.julia_param,
+			julia_mode: self.julia_mode,
+			inverse: self.inverse,
+			noise: self.noise,
+			apollonian: self.apollonian,
+			kleinian: self.kleinian,
+			hybrid: self.hybrid.clone(),
+			coloring: self.coloring.clone(),
+			trap: self.trap,
+			averages: self.averages,
+			generator: self.generator,
 		};
 		export_favorite(&fav)
 	}
@@ -161,10 +863,24 @@ impl FractalApp {
 	/// Import a favorite view and settings from a JSON file.
 	pub fn import_favorite(&mut self, path: &str, ctx: &egui::Context) -> Result<(), String> {
 		let fav = import_favorite(path)?;
+		check_apollonian_radii(&fav.apollonian)?;
 		self.view = fav.view;
 		self.palette = fav.palette;
+		self.gradient = fav.gradient;
+		self.selected_stop = None;
 		self.fractal_type = fav.fractal_type;
 		self.julia_param = fav.julia_param;
+		self.julia_mode = fav.julia_mode;
+		self.inverse = fav.inverse;
+		self.noise = fav.noise;
+		self.apollonian = fav.apollonian;
+		self.kleinian = fav.kleinian;
+		self.hybrid = fav.hybrid;
+		self.coloring = fav.coloring;
+		self.trap = fav.trap;
+		self.averages = fav.averages;
+		self.generator = fav.generator;
+		self.load_texture_image();
 		self.rerender(ctx);
 		Ok(())
 	}
@@ -175,26 +891,65 @@ impl eframe::App for FractalApp {
 	/// Handles all UI controls, rendering, and user interaction.
 	fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
 	// show_import_dialog is now a struct field
+		if self.cycling {
+			// Advance the palette offset by the frame time and recolor the cached samples
+			let direction = if self.cycle_reverse { -1.0 } else { 1.0 };
+			let dt = ctx.input(|i| i.stable_dt) as f64;
+			self.coloring.offset = (self.coloring.offset + direction * self.cycle_speed * dt).rem_euclid(1.0);
+			self.recolor(ctx);
+			ctx.request_repaint();
+		}
 		egui::CentralPanel::default().show(ctx, |ui| {
 			// Layout: vertical stack, with a horizontal toolbar for controls
 			ui.vertical(|ui| {
 				// Toolbar: palette, fractal type, Julia params, buttons
 				ui.horizontal(|ui| {
+					let custom = self.selected_custom.and_then(|i| self.palette_list.get(i));
+					let selected_name = match custom {
+						Some(named) if self.palette == Palette::UserDefined => named.name.clone(),
+						_ => PALETTE_NAMES.iter().find(|(_, p)| *p == self.palette).map(|(n, _)| *n).unwrap_or("Unknown").to_string(),
+					};
+					let mut picked_custom = None;
 					egui::ComboBox::from_label("")
-						.selected_text(
-							PALETTE_NAMES.iter().find(|(_, p)| *p == self.palette).map(|(n, _)| *n).unwrap_or("Unknown")
-						)
+						.selected_text(selected_name)
 						.show_ui(ui, |cb| {
 							for (name, pal) in PALETTE_NAMES {
-								cb.selectable_value(&mut self.palette, *pal, *name);
+								let selected = self.palette == *pal && self.selected_custom.is_none();
+								if cb.selectable_label(selected, *name).clicked() {
+									self.palette = *pal;
+									self.selected_custom = None;
+								}
+							}
+							if !self.palette_list.is_empty() {
+								cb.separator();
+								for (i, named) in self.palette_list.iter().enumerate() {
+									if cb.selectable_label(self.selected_custom == Some(i), &named.name).clicked() {
+										picked_custom = Some(i);
+									}
+								}
 							}
 						});
+					if let Some(i) = picked_custom {
+						self.select_custom_palette(i, ctx);
+					}
 
 					ui.label("Fractal:");
-					ui.selectable_value(&mut self.fractal_type, FractalType::Mandelbrot, "Mandelbrot");
-					ui.selectable_value(&mut self.fractal_type, FractalType::Julia, "Julia");
+					let mut type_changed = false;
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Mandelbrot, "Mandelbrot").changed();
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Julia, "Julia").changed();
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Noise, "Noise").changed();
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Apollonian, "Apollonian").changed();
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Kleinian, "Kleinian").changed();
+					type_changed |= ui.selectable_value(&mut self.fractal_type, FractalType::Hybrid, "Hybrid").changed();
+					if type_changed {
+						self.rerender(ctx);
+					}
+
+					if self.fractal_type == FractalType::Mandelbrot {
+						ui.checkbox(&mut self.linked_julia, "Julia Preview");
+					}
 
-					if self.fractal_type == FractalType::Julia {
+					if self.fractal_type == FractalType::Julia || (self.fractal_type == FractalType::Hybrid && self.hybrid.julia) {
 						ui.label("c (re, im):");
 						let mut re = self.julia_param.0;
 						let mut im = self.julia_param.1;
@@ -227,13 +982,23 @@ impl eframe::App for FractalApp {
 						};
 						self.rerender(ctx);
 					}
-					if ui.button("Redraw").clicked() || self.palette != self.last_palette {
-						self.last_palette = self.palette;
+					if ui.button("Redraw").clicked() {
 						self.rerender(ctx);
 					}
+					if self.palette != self.last_palette {
+						self.last_palette = self.palette;
+						self.recolor(ctx);
+					}
 
-					if ui.button("Save PNG").clicked() {
-						match save_fractal_serialized(self.width, self.height, self.view, self.palette, &self.user_palette, self.fractal_type, self.julia_param, false) {
+					egui::ComboBox::from_id_source("export_format")
+						.selected_text(EXPORT_FORMAT_NAMES.iter().find(|(_, f)| *f == self.export_format).map(|(n, _)| *n).unwrap_or("Unknown"))
+						.show_ui(ui, |cb| {
+							for (name, format) in EXPORT_FORMAT_NAMES {
+								cb.selectable_value(&mut self.export_format, *format, *name);
+							}
+						});
+					if ui.button("Save Image").clicked() {
+						match save_fractal_serialized(self.width, self.height, &self.render_settings(), false, self.export_format) {
 							Ok(path) => self.save_message = Some(format!("Saved as {}", path)),
 							Err(e) => self.save_message = Some(format!("Failed to save: {e}")),
 						}
@@ -256,19 +1021,16 @@ impl eframe::App for FractalApp {
 								self.save_message = Some("Error: Save thread panicked (mutex poisoned)".to_string());
 							}
 						}
-					} else if ui.button("Save High-Res PNG").clicked() {
+					} else if ui.button("Save High-Res").clicked() {
 						self.highres_in_progress = true;
 						self.save_message = None;
 						let width = 3200;
 						let height = 2400;
-						let view = self.view;
-						let palette = self.palette;
-						let fractal_type = self.fractal_type;
-						let julia_param = self.julia_param;
-						let user_palette = self.user_palette;
+						let settings = self.render_settings();
+						let format = self.export_format;
 						let result_arc = self.highres_result.clone();
 						std::thread::spawn(move || {
-							let result = save_fractal_serialized(width, height, view, palette, &user_palette, fractal_type, julia_param, true);
+							let result = save_fractal_serialized(width, height, &settings, true, format);
 							if let Ok(mut lock) = result_arc.lock() {
 								*lock = Some(result);
 							}
@@ -286,34 +1048,510 @@ impl eframe::App for FractalApp {
 					}
 				});
 
-				if self.palette == Palette::UserDefined {
+				if self.fractal_type == FractalType::Julia {
+					ui.horizontal(|ui| {
+						let mut changed = false;
+						ui.label("Julia render:");
+						changed |= ui.selectable_value(&mut self.julia_mode, JuliaMode::EscapeTime, "Escape-time").changed();
+						changed |= ui.selectable_value(&mut self.julia_mode, JuliaMode::Inverse, "Inverse (MIIM)").changed();
+						changed |= ui.selectable_value(&mut self.julia_mode, JuliaMode::Overlay, "Overlay").changed();
+						if self.julia_mode != JuliaMode::EscapeTime {
+							ui.label("Max hits per pixel:");
+							changed |= ui.add(egui::DragValue::new(&mut self.inverse.max_hits).clamp_range(1..=100)).changed();
+							ui.label("Max depth:");
+							changed |= ui.add(egui::DragValue::new(&mut self.inverse.max_depth).clamp_range(1..=500)).changed();
+						}
+						if changed {
+							self.rerender(ctx);
+						}
+					});
+				}
+
+				if self.fractal_type == FractalType::Noise {
 					ui.horizontal(|ui| {
-						ui.label("User Palette: Pick two colors for the gradient");
-						let mut color1 = [self.user_palette[0].0 as f32 / 255.0, self.user_palette[0].1 as f32 / 255.0, self.user_palette[0].2 as f32 / 255.0];
-						let mut color2 = [self.user_palette[1].0 as f32 / 255.0, self.user_palette[1].1 as f32 / 255.0, self.user_palette[1].2 as f32 / 255.0];
-						let changed1 = ui.color_edit_button_rgb(&mut color1).changed();
-						let changed2 = ui.color_edit_button_rgb(&mut color2).changed();
-						if changed1 {
-							self.user_palette[0] = ((color1[0] * 255.0) as u8, (color1[1] * 255.0) as u8, (color1[2] * 255.0) as u8);
+						let mut changed = false;
+						ui.label("Noise:");
+						changed |= ui.selectable_value(&mut self.noise.kind, NoiseKind::Fbm, "fBm").changed();
+						changed |= ui.selectable_value(&mut self.noise.kind, NoiseKind::Ridged, "Ridged").changed();
+						changed |= ui.selectable_value(&mut self.noise.kind, NoiseKind::Plasma, "Plasma").changed();
+						ui.label("Octaves:");
+						changed |= ui.add(egui::DragValue::new(&mut self.noise.octaves).clamp_range(1..=16)).changed();
+						ui.label("Lacunarity:");
+						changed |= ui.add(egui::DragValue::new(&mut self.noise.lacunarity).speed(0.01).clamp_range(1.0..=4.0)).changed();
+						ui.label("Gain:");
+						changed |= ui.add(egui::DragValue::new(&mut self.noise.gain).speed(0.01).clamp_range(0.0..=1.0)).changed();
+						ui.label("Seed:");
+						changed |= ui.add(egui::DragValue::new(&mut self.noise.seed)).changed();
+						if changed {
 							self.rerender(ctx);
 						}
-						if changed2 {
-							self.user_palette[1] = ((color2[0] * 255.0) as u8, (color2[1] * 255.0) as u8, (color2[2] * 255.0) as u8);
+						if ui.button("Save Heightmap").clicked() {
+							match save_heightmap(self.width, self.height, self.view, &self.noise, false) {
+								Ok(path) => self.save_message = Some(format!("Saved heightmap as {}", path)),
+								Err(e) => self.save_message = Some(format!("Failed to save: {e}")),
+							}
+						}
+					});
+				}
+
+				if self.fractal_type == FractalType::Apollonian {
+					ui.horizontal(|ui| {
+						let mut changed = false;
+						ui.label("Circle radii:");
+						let previous = self.apollonian.radii;
+						for r in self.apollonian.radii.iter_mut() {
+							changed |= ui.add(egui::DragValue::new(r).speed(0.01).clamp_range(0.01..=100.0)).changed();
+						}
+						if changed {
+							// Keep the last radii that still have an enclosing circle
+							match check_apollonian_radii(&self.apollonian) {
+								Ok(()) => self.rerender(ctx),
+								Err(e) => {
+									self.apollonian.radii = previous;
+									self.save_message = Some(e);
+								}
+							}
+						}
+					});
+				}
+
+				if self.fractal_type == FractalType::Kleinian {
+					let mut changed = false;
+					ui.horizontal(|ui| {
+						ui.label("Traces ta, tb (re, im):");
+						let ((ta_re, ta_im), (tb_re, tb_im)) = &mut self.kleinian_traces;
+						for v in [ta_re, ta_im, tb_re, tb_im] {
+							ui.add(egui::DragValue::new(v).speed(0.01));
+						}
+						if ui.button("Grandma's Recipe").clicked() {
+							let (ta, tb) = self.kleinian_traces;
+							(self.kleinian.a, self.kleinian.b) = grandma_recipe(ta, tb);
+							changed = true;
+						}
+						ui.label("Max depth:");
+						changed |= ui.add(egui::DragValue::new(&mut self.kleinian.max_depth).clamp_range(1..=40)).changed();
+					});
+					ui.horizontal(|ui| {
+						changed |= Self::mobius_editor(ui, "a =", &mut self.kleinian.a);
+						changed |= Self::mobius_editor(ui, "b =", &mut self.kleinian.b);
+					});
+					if changed {
+						self.rerender(ctx);
+					}
+				}
+
+				if self.fractal_type == FractalType::Hybrid && self.hybrid_editor(ui) {
+					self.rerender(ctx);
+				}
+
+				ui.horizontal(|ui| {
+					let mut changed = false;
+					let orbits = self.tracks_orbits();
+					ui.label("Coloring:");
+					let mut algorithm_changed = false;
+					egui::ComboBox::from_id_source("color_algorithm")
+						.selected_text(
+							COLOR_ALGORITHM_NAMES.iter().find(|(_, a)| *a == self.coloring.algorithm).map(|(n, _)| *n).unwrap_or("Unknown")
+						)
+						.show_ui(ui, |cb| {
+							for (name, algorithm) in COLOR_ALGORITHM_NAMES {
+								let needs_orbit = algorithm.exponential_index().is_some();
+								algorithm_changed |= Self::orbit_option(cb, &mut self.coloring.algorithm, *algorithm, name, needs_orbit, orbits);
+							}
+						});
+					if !orbits && self.coloring.algorithm.exponential_index().is_some() {
+						ui.label(ORBIT_ONLY_HINT);
+					}
+					if algorithm_changed | Self::transfer_editor(ui, "transfer", &mut self.coloring.transfer) {
+						self.recolor(ctx);
+					}
+					if ui.checkbox(&mut self.coloring.smooth, "Smooth").changed() {
+						// A frozen histogram of whole iterations does not fit smooth values
+						if self.coloring.frozen_histogram.is_some() {
+							self.freeze_histogram();
+						}
+						changed = true;
+					}
+					changed |= ui.checkbox(&mut self.coloring.histogram, "Histogram").changed();
+					if self.coloring.histogram {
+						let mut frozen = self.coloring.frozen_histogram.is_some();
+						if ui.checkbox(&mut frozen, "Freeze histogram").changed() {
+							self.coloring.frozen_histogram = None;
+							if frozen {
+								self.freeze_histogram();
+							}
+							changed = true;
+						}
+					}
+					// Smoothing and the histogram only change how the cached samples are colored
+					if changed {
+						self.recolor(ctx);
+					}
+				});
+
+				if matches!(self.fractal_type, FractalType::Mandelbrot | FractalType::Julia) {
+					ui.horizontal(|ui| {
+						let mut changed = false;
+						let distance = &mut self.coloring.distance;
+						ui.label("Distance estimate:");
+						changed |= ui.selectable_value(&mut distance.mode, DistanceMode::Off, "Off").changed();
+						changed |= ui.selectable_value(&mut distance.mode, DistanceMode::Lines, "Lines").changed();
+						changed |= ui.selectable_value(&mut distance.mode, DistanceMode::Shading, "Shading").changed();
+						changed |= ui.selectable_value(&mut distance.mode, DistanceMode::Fill, "Fill").changed();
+						if distance.mode != DistanceMode::Off {
+							ui.label(if distance.mode == DistanceMode::Shading { "Falloff:" } else { "Thickness:" });
+							changed |= ui.add(egui::DragValue::new(&mut distance.thickness).speed(0.05).clamp_range(0.05..=500.0).suffix(" px")).changed();
+						}
+						if distance.mode == DistanceMode::Lines {
+							let mut rgb = [distance.color.0, distance.color.1, distance.color.2];
+							if ui.color_edit_button_srgb(&mut rgb).changed() {
+								distance.color = (rgb[0], rgb[1], rgb[2]);
+								changed = true;
+							}
+						}
+						if changed {
+							self.recolor(ctx);
+						}
+					});
+				}
+
+				ui.horizontal(|ui| {
+					let mut changed = false;
+					let lighting = &mut self.coloring.lighting;
+					changed |= ui.checkbox(&mut lighting.enabled, "Lighting").changed();
+					if lighting.enabled {
+						changed |= Self::light_dial(ui, lighting);
+						ui.label("Azimuth:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.azimuth).speed(1.0).suffix("°")).changed();
+						ui.label("Elevation:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.elevation).speed(1.0).clamp_range(0.0..=90.0).suffix("°")).changed();
+						ui.label("Ambient:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.ambient).speed(0.01).clamp_range(0.0..=2.0)).changed();
+						ui.label("Diffuse:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.diffuse).speed(0.01).clamp_range(0.0..=2.0)).changed();
+						ui.label("Specular:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.specular).speed(0.01).clamp_range(0.0..=2.0)).changed();
+						ui.label("Shininess:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.shininess).speed(0.5).clamp_range(1.0..=200.0)).changed();
+						ui.label("Height:");
+						changed |= ui.add(egui::DragValue::new(&mut lighting.height).speed(0.05).clamp_range(0.0..=20.0)).changed();
+					}
+					if changed {
+						self.recolor(ctx);
+					}
+				});
+
+				ui.horizontal(|ui| {
+					ui.label("Layers:");
+					if ui.button("Add layer").clicked() {
+						self.coloring.layers.push(ColorLayer::default());
+						self.recolor(ctx);
+					}
+				});
+				let mut changed = false;
+				let mut remove = None;
+				let mut swap = None;
+				let count = self.coloring.layers.len();
+				let orbits = self.tracks_orbits();
+				for (i, layer) in self.coloring.layers.iter_mut().enumerate() {
+					ui.horizontal(|ui| {
+						ui.label(format!("{}.", i + 1));
+						changed |= ui.checkbox(&mut layer.enabled, "").changed();
+						egui::ComboBox::from_id_source(("layer_algorithm", i))
+							.selected_text(
+								COLOR_ALGORITHM_NAMES.iter().find(|(_, a)| *a == layer.algorithm).map(|(n, _)| *n).unwrap_or("Unknown")
+							)
+							.show_ui(ui, |cb| {
+								for (name, algorithm) in COLOR_ALGORITHM_NAMES {
+									let needs_orbit = algorithm.exponential_index().is_some();
+									changed |= Self::orbit_option(cb, &mut layer.algorithm, *algorithm, name, needs_orbit, orbits);
+								}
+							});
+						let palette_name = match &layer.custom {
+							Some(named) => named.name.clone(),
+							None => PALETTE_NAMES.iter().find(|(_, p)| *p == layer.palette).map(|(n, _)| *n).unwrap_or("Unknown").to_string(),
+						};
+						egui::ComboBox::from_id_source(("layer_palette", i))
+							.selected_text(palette_name)
+							.show_ui(ui, |cb| {
+								for (name, pal) in PALETTE_NAMES {
+									if cb.selectable_label(layer.custom.is_none() && layer.palette == *pal, *name).clicked() {
+										layer.palette = *pal;
+										layer.custom = None;
+										changed = true;
+									}
+								}
+								if !self.palette_list.is_empty() {
+									cb.separator();
+									for named in &self.palette_list {
+										if cb.selectable_label(layer.custom.as_ref() == Some(named), &named.name).clicked() {
+											layer.custom = Some(named.clone());
+											changed = true;
+										}
+									}
+								}
+							});
+						changed |= Self::transfer_editor(ui, ("layer_transfer", i), &mut layer.transfer);
+						egui::ComboBox::from_id_source(("layer_blend", i))
+							.selected_text(
+								BLEND_MODE_NAMES.iter().find(|(_, m)| *m == layer.blend).map(|(n, _)| *n).unwrap_or("Unknown")
+							)
+							.show_ui(ui, |cb| {
+								for (name, mode) in BLEND_MODE_NAMES {
+									changed |= cb.selectable_value(&mut layer.blend, *mode, *name).changed();
+								}
+							});
+						ui.label("Opacity:");
+						changed |= ui.add(egui::Slider::new(&mut layer.opacity, 0.0..=1.0)).changed();
+						if ui.add_enabled(i + 1 < count, egui::Button::new("Raise")).clicked() {
+							swap = Some(i);
+						}
+						if ui.add_enabled(i > 0, egui::Button::new("Lower")).clicked() {
+							swap = Some(i - 1);
+						}
+						if ui.button("Remove").clicked() {
+							remove = Some(i);
+						}
+					});
+				}
+				// Layers are listed bottom to top, so raising a layer moves it later in the stack
+				if let Some(i) = swap {
+					self.coloring.layers.swap(i, i + 1);
+					changed = true;
+				}
+				if let Some(i) = remove {
+					self.coloring.layers.remove(i);
+					changed = true;
+				}
+				if changed {
+					self.recolor(ctx);
+				}
+
+				if self.coloring.uses_averages().contains(&true) {
+					ui.horizontal(|ui| {
+						ui.label("Averages:");
+						if self.coloring.algorithm == ColorAlgorithm::StripeAverage
+							|| self.coloring.layers.iter().any(|l| l.enabled && l.algorithm == ColorAlgorithm::StripeAverage)
+						{
+							ui.label("Stripe density:");
+							if ui.add(egui::DragValue::new(&mut self.averages.stripe_density).speed(0.1).clamp_range(0.0..=50.0)).changed() {
+								self.rerender(ctx);
+							}
+						}
+						ui.label("Blend with smooth iteration:");
+						if ui.add(egui::Slider::new(&mut self.averages.blend, 0.0..=1.0)).changed() {
+							self.recolor(ctx);
+						}
+					});
+				}
+
+				if self.coloring.algorithm == ColorAlgorithm::AngleSectors
+					|| self.coloring.layers.iter().any(|l| l.enabled && l.algorithm == ColorAlgorithm::AngleSectors)
+				{
+					ui.horizontal(|ui| {
+						ui.label("Angle sectors:");
+						if ui.add(egui::DragValue::new(&mut self.coloring.decomposition.sectors).clamp_range(2..=64)).changed() {
+							self.recolor(ctx);
+						}
+					});
+				}
+
+				if self.coloring.uses_trap() {
+					ui.horizontal(|ui| {
+						let mut changed = false;
+						ui.label("Orbit trap:");
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Point, "Point").changed();
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Line, "Line").changed();
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Cross, "Cross").changed();
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Circle, "Circle").changed();
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Ring, "Ring").changed();
+						changed |= ui.selectable_value(&mut self.trap.shape, TrapShape::Grid, "Grid").changed();
+						ui.label("Center:");
+						changed |= ui.add(egui::DragValue::new(&mut self.trap.center.0).speed(0.01)).changed();
+						changed |= ui.add(egui::DragValue::new(&mut self.trap.center.1).speed(0.01)).changed();
+						ui.label("Size:");
+						changed |= ui.add(egui::DragValue::new(&mut self.trap.size).speed(0.01).clamp_range(0.001..=10.0)).changed();
+						if matches!(self.trap.shape, TrapShape::Line | TrapShape::Cross | TrapShape::Grid) {
+							ui.label("Angle:");
+							changed |= ui.add(egui::DragValue::new(&mut self.trap.angle).speed(1.0).suffix("°")).changed();
+						}
+						if self.trap.shape == TrapShape::Ring {
+							ui.label("Width:");
+							changed |= ui.add(egui::DragValue::new(&mut self.trap.width).speed(0.01).clamp_range(0.0..=10.0)).changed();
+						}
+						if changed {
 							self.rerender(ctx);
 						}
 					});
 				}
+
+				ui.horizontal(|ui| {
+					let mut changed = false;
+					let orbits = self.tracks_orbits();
+					let interior = &mut self.coloring.interior;
+					ui.label("Interior:");
+					egui::ComboBox::from_id_source("interior_mode")
+						.selected_text(
+							INTERIOR_MODE_NAMES.iter().find(|(_, m)| *m == interior.mode).map(|(n, _)| *n).unwrap_or("Unknown")
+						)
+						.show_ui(ui, |cb| {
+							for (name, mode) in INTERIOR_MODE_NAMES {
+								let needs_orbit = *mode == InteriorMode::ExponentialSmoothing;
+								changed |= Self::orbit_option(cb, &mut interior.mode, *mode, name, needs_orbit, orbits);
+							}
+						});
+					if interior.mode != InteriorMode::Solid {
+						egui::ComboBox::from_id_source("interior_palette")
+							.selected_text(
+								PALETTE_NAMES.iter().find(|(_, p)| *p == interior.palette).map(|(n, _)| *n).unwrap_or("Unknown")
+							)
+							.show_ui(ui, |cb| {
+								for (name, pal) in PALETTE_NAMES {
+									changed |= cb.selectable_value(&mut interior.palette, *pal, *name).changed();
+								}
+							});
+					}
+					// Also used for points whose cycle was not found
+					ui.label("Color:");
+					let mut rgb = [interior.color.0, interior.color.1, interior.color.2];
+					if ui.color_edit_button_srgb(&mut rgb).changed() {
+						interior.color = (rgb[0], rgb[1], rgb[2]);
+						changed = true;
+					}
+					if changed {
+						self.recolor(ctx);
+					}
+				});
+
+				ui.horizontal(|ui| {
+					let mut changed = false;
+					let transparency = &mut self.coloring.transparency;
+					ui.label("Transparent:");
+					changed |= ui.checkbox(&mut transparency.interior, "Interior").changed();
+					changed |= ui.checkbox(&mut transparency.range, "Iterations").changed();
+					if transparency.range {
+						changed |= ui.add(egui::DragValue::new(&mut transparency.min).speed(0.1).clamp_range(0.0..=255.0)).changed();
+						ui.label("to");
+						changed |= ui.add(egui::DragValue::new(&mut transparency.max).speed(0.1).clamp_range(0.0..=255.0)).changed();
+					}
+					if changed {
+						self.recolor(ctx);
+					}
+				});
+
+				ui.horizontal(|ui| {
+					let mut changed = false;
+					let texture = &mut self.coloring.texture;
+					ui.label("Texture:");
+					changed |= ui.checkbox(&mut texture.enabled, "").changed();
+					if ui.button("Choose Image").clicked() {
+						self.show_texture_dialog = true;
+					}
+					if texture.path.is_empty() {
+						ui.label("(none)");
+					} else {
+						ui.label(&texture.path);
+					}
+					if texture.enabled {
+						changed |= ui.selectable_value(&mut texture.source, TextureSource::FinalZ, "Final z").changed();
+						changed |= ui.selectable_value(&mut texture.source, TextureSource::OrbitTrap, "Orbit trap").changed();
+						changed |= ui.selectable_value(&mut texture.wrap, RepeatMode::Repeat, "Tile").changed();
+						changed |= ui.selectable_value(&mut texture.wrap, RepeatMode::Mirror, "Mirror").changed();
+						changed |= ui.selectable_value(&mut texture.wrap, RepeatMode::Clamp, "Clamp").changed();
+						ui.label("Scale:");
+						changed |= ui.add(egui::DragValue::new(&mut texture.scale).speed(0.01).clamp_range(0.01..=100.0)).changed();
+						ui.label("Offset:");
+						changed |= ui.add(egui::DragValue::new(&mut texture.offset.0).speed(0.01)).changed();
+						changed |= ui.add(egui::DragValue::new(&mut texture.offset.1).speed(0.01)).changed();
+						changed |= ui.checkbox(&mut texture.interior, "Interior").changed();
+						egui::ComboBox::from_id_source("texture_blend")
+							.selected_text(
+								BLEND_MODE_NAMES.iter().find(|(_, m)| *m == texture.blend).map(|(n, _)| *n).unwrap_or("Unknown")
+							)
+							.show_ui(ui, |cb| {
+								for (name, mode) in BLEND_MODE_NAMES {
+									changed |= cb.selectable_value(&mut texture.blend, *mode, *name).changed();
+								}
+							});
+						ui.label("Opacity:");
+						changed |= ui.add(egui::Slider::new(&mut texture.opacity, 0.0..=1.0)).changed();
+					}
+					if changed {
+						self.recolor(ctx);
+					}
+				});
+
+				ui.horizontal(|ui| {
+					ui.label("Color cycling:");
+					if ui.button(if self.cycling { "Pause" } else { "Play" }).clicked() {
+						self.cycling = !self.cycling;
+					}
+					ui.label("Speed:");
+					ui.add(egui::DragValue::new(&mut self.cycle_speed).speed(0.01).clamp_range(0.0..=10.0));
+					ui.checkbox(&mut self.cycle_reverse, "Reverse");
+					ui.label("Offset:");
+					if ui.add(egui::DragValue::new(&mut self.coloring.offset).speed(0.005).clamp_range(0.0..=1.0)).changed() {
+						self.recolor(ctx);
+					}
+				});
+
+				if self.palette == Palette::UserDefined && gradient_editor(ui, &mut self.gradient, &mut self.selected_stop) {
+					// Edits to a loaded palette are kept in the palette list
+					if let Some(named) = self.selected_custom.and_then(|i| self.palette_list.get_mut(i)) {
+						named.gradient = self.gradient.clone();
+						self.palette_library_dirty = true;
+					}
+					self.recolor(ctx);
+				}
+				// Write the library once the drag or text entry making the edit has ended
+				if self.palette_library_dirty && !ctx.input(|i| i.pointer.any_down()) && ctx.memory(|m| m.focused().is_none()) {
+					self.palette_library_dirty = false;
+					self.store_palette_library();
+				}
+				self.palette_library_row(ui, ctx);
+				self.palette_generator_row(ui, ctx);
+				ui.horizontal(|ui| {
+					if ui.button("Import Palette").clicked() {
+						self.show_palette_import_dialog = true;
+					}
+					if ui.button("Extract from Image").clicked() {
+						self.show_extract_dialog = true;
+					}
+					if self.palette == Palette::UserDefined {
+						ui.label("Export palette:");
+						for format in [PaletteFormat::Map, PaletteFormat::Ggr, PaletteFormat::Ugr] {
+							if ui.button(format!(".{}", format.extension())).clicked() {
+								match self.export_palette(format) {
+									Ok(path) => self.save_message = Some(format!("Palette saved as {}", path)),
+									Err(e) => self.save_message = Some(format!("Failed to export: {e}")),
+								}
+							}
+						}
+					}
+				});
 				// Show the import favorites dialog if requested (now global, not just for user palette)
 				if self.show_import_dialog {
 					self.show_import_favorite_dialog(ctx);
 				}
+				if self.show_palette_import_dialog {
+					self.show_import_palette_dialog(ctx);
+				}
+				if self.show_extract_dialog {
+					self.show_extract_palette_dialog(ctx);
+				}
+				if self.show_texture_dialog {
+					self.show_texture_image_dialog(ctx);
+				}
 
 				if let Some(msg) = &self.save_message {
 					ui.label(msg);
 				}
 
 				let image_size = egui::vec2(self.width as f32, self.height as f32);
-				let (rect, response) = ui.allocate_exact_size(image_size, egui::Sense::drag());
+				let (rect, response) = ui.allocate_exact_size(image_size, egui::Sense::click_and_drag());
+				if self.coloring.transparency.enabled() {
+					Self::checkerboard(ui, rect);
+				}
 				if let Some(texture) = &self.texture_handle {
 					ui.painter().image(
 						texture.id(),
@@ -323,14 +1561,15 @@ impl eframe::App for FractalApp {
 					);
 				}
 
-				if response.drag_started() {
-					if let Some(pos) = response.interact_pointer_pos() {
-						if rect.contains(pos) {
-							self.dragging = true;
-							self.drag_start = Some(pos);
-							self.drag_end = Some(pos);
-						}
-					}
+				let trap_dragging = self.coloring.uses_trap() && self.trap_overlay(ctx, ui, rect, &response);
+				if response.drag_started()
+					&& !trap_dragging
+					&& let Some(pos) = response.interact_pointer_pos()
+					&& rect.contains(pos)
+				{
+					self.dragging = true;
+					self.drag_start = Some(pos);
+					self.drag_end = Some(pos);
 				}
 				if self.dragging {
 					if let Some(pos) = response.interact_pointer_pos() {
@@ -349,13 +1588,8 @@ impl eframe::App for FractalApp {
 						let min = start.min(end);
 						let max = start.max(end);
 						if (max.x - min.x).abs() > 5.0 && (max.y - min.y).abs() > 5.0 {
-							let to_fractal = |pos: egui::Pos2| {
-								let x = self.view.min_x + ((pos.x - rect.min.x) / rect.width()) as f64 * (self.view.max_x - self.view.min_x);
-								let y = self.view.min_y + ((pos.y - rect.min.y) / rect.height()) as f64 * (self.view.max_y - self.view.min_y);
-								(x, y)
-							};
-							let (min_x, min_y) = to_fractal(min);
-							let (max_x, max_y) = to_fractal(max);
+							let (min_x, min_y) = self.to_plane(rect, min);
+							let (max_x, max_y) = self.to_plane(rect, max);
 							self.view = ViewRect {
 								min_x,
 								max_x,
@@ -368,6 +1602,10 @@ impl eframe::App for FractalApp {
 					self.drag_start = None;
 					self.drag_end = None;
 				}
+
+				if self.linked_julia && self.fractal_type == FractalType::Mandelbrot {
+					self.linked_julia_overlay(ctx, ui, rect, &response);
+				}
 			});
 		});
 	}
@@ -0,0 +1,354 @@
+//! Circle-inversion fractals: the Apollonian gasket and Kleinian group limit sets.
+//! The gasket is drawn by inversion escape-time, the limit sets by depth-first
+//! enumeration of group words as described in "Indra's Pearls".
+
+use crate::complex::Complex;
+use crate::types::{ViewRect, ApollonianParams, KleinianParams, Mobius};
+
+/// A circle with center and radius.
+#[derive(Clone, Copy)]
+struct Circle {
+	center: Complex,
+	radius: f64,
+}
+
+impl Circle {
+	/// Circle through three points (their circumcircle).
+	fn through(p1: Complex, p2: Complex, p3: Complex) -> Circle {
+		let d = 2.0 * (p1.re * (p2.im - p3.im) + p2.re * (p3.im - p1.im) + p3.re * (p1.im - p2.im));
+		let (s1, s2, s3) = (p1.norm_sqr(), p2.norm_sqr(), p3.norm_sqr());
+		let center = Complex::new(
+			(s1 * (p2.im - p3.im) + s2 * (p3.im - p1.im) + s3 * (p1.im - p2.im)) / d,
+			(s1 * (p3.re - p2.re) + s2 * (p1.re - p3.re) + s3 * (p2.re - p1.re)) / d,
+		);
+		Circle { center, radius: (p1 - center).abs() }
+	}
+
+	/// Invert `z` in this circle.
+	fn invert(&self, z: Complex) -> Complex {
+		let d = z - self.center;
+		self.center + d * (self.radius * self.radius / d.norm_sqr())
+	}
+}
+
+/// A dual circle of the gasket. One of the four duals may enclose the other three,
+/// in which case the region it acts on is the outside of the circle.
+#[derive(Clone, Copy)]
+struct Dual {
+	circle: Circle,
+	encloses: bool,
+}
+
+impl Dual {
+	/// Does this dual's region contain `z` (so `z` should be inverted)?
+	fn captures(&self, z: Complex) -> bool {
+		let inside = (z - self.circle.center).norm_sqr() < self.circle.radius * self.circle.radius;
+		inside != self.encloses
+	}
+}
+
+/// Palette steps between successive inversion depths, so shallow depths stay distinguishable.
+const DEPTH_COLOR_STEP: u32 = 24;
+
+/// Curvature of the fourth circle tangent to three externally tangent circles with
+/// curvatures `k`, from the minus root of the Descartes circle theorem.
+/// Negative when the fourth circle encloses the other three.
+fn outer_curvature(k: [f64; 3]) -> f64 {
+	k[0] + k[1] + k[2] - 2.0 * (k[0] * k[1] + k[1] * k[2] + k[2] * k[0]).sqrt()
+}
+
+/// Check that the starting radii leave room for an enclosing circle.
+///
+/// When one circle is small enough to sit in the gap between the other two, the fourth
+/// tangent circle becomes a line (curvature 0) or another circle outside them
+/// (positive curvature), and the gasket is unbounded.
+pub fn check_apollonian_radii(params: &ApollonianParams) -> Result<(), String> {
+	let k = params.radii.map(|r| 1.0 / r.abs().max(1e-6));
+	// Relative tolerance, so nearly straight outer circles are rejected at any scale
+	if outer_curvature(k) >= -1e-6 * (k[0] + k[1] + k[2]) {
+		return Err("The smallest circle fits in the gap between the other two, so no circle encloses them; enlarge it".to_string());
+	}
+	Ok(())
+}
+
+/// Build the four mutually tangent circles of the gasket and their four dual circles.
+///
+/// The gasket is normalized so its outer circle is the unit circle at the origin.
+/// Inversions in the dual circles generate the Apollonian group.
+///
+/// Returns `(circles, duals)`.
+fn apollonian_circles(params: &ApollonianParams) -> ([Circle; 4], [Dual; 4]) {
+	let [r1, r2, r3] = params.radii.map(|r| r.abs().max(1e-6));
+	// Place the three circles so each pair is externally tangent
+	let (d12, d13, d23) = (r1 + r2, r1 + r3, r2 + r3);
+	let x3 = (d13 * d13 - d23 * d23 + d12 * d12) / (2.0 * d12);
+	let z = [
+		Complex::new(0.0, 0.0),
+		Complex::new(d12, 0.0),
+		Complex::new(x3, (d13 * d13 - x3 * x3).max(0.0).sqrt()),
+	];
+	let k = [1.0 / r1, 1.0 / r2, 1.0 / r3];
+
+	// Outer circle from the Descartes circle theorem (negative curvature = encloses the
+	// others; `check_apollonian_radii` rejects radii where it does not)
+	let k4 = outer_curvature(k);
+	let sum = z[0] * k[0] + z[1] * k[1] + z[2] * k[2];
+	let root = (z[0] * z[1] * (k[0] * k[1]) + z[1] * z[2] * (k[1] * k[2]) + z[0] * z[2] * (k[0] * k[2])).sqrt() * 2.0;
+	let r4 = 1.0 / k4.abs();
+	// Pick the sign of the square root that actually gives internal tangency
+	let tangency_error = |c: Complex| ((c - z[0]).abs() - (r4 - r1)).abs();
+	let z4 = [(sum + root) / k4, (sum - root) / k4]
+		.into_iter()
+		.min_by(|a, b| tangency_error(*a).total_cmp(&tangency_error(*b)))
+		.unwrap_or_default();
+
+	// Normalize so the outer circle is the unit circle
+	let centers = [z[0], z[1], z[2], z4].map(|c| (c - z4) / r4);
+	let curvatures = [k[0] * r4, k[1] * r4, k[2] * r4, k4 * r4];
+	let circles = std::array::from_fn(|i| Circle { center: centers[i], radius: 1.0 / curvatures[i].abs() });
+
+	// Tangency point of circles i and j, weighted by signed curvature
+	let touch = |i: usize, j: usize| {
+		(centers[i] * curvatures[i] + centers[j] * curvatures[j]) / (curvatures[i] + curvatures[j])
+	};
+	// Dual circle k passes through the three tangency points not involving circle k
+	let dual = |skip: usize| {
+		let others: Vec<usize> = (0..4).filter(|&i| i != skip).collect();
+		Circle::through(touch(others[0], others[1]), touch(others[0], others[2]), touch(others[1], others[2]))
+	};
+	let duals = [dual(0), dual(1), dual(2), dual(3)];
+	let encloses = |k: usize| {
+		let outer = duals[k];
+		duals.iter().enumerate().filter(|&(j, _)| j != k).all(|(_, d)| {
+			(d.center - outer.center).abs() + d.radius <= outer.radius * (1.0 + 1e-9)
+		})
+	};
+	(circles, std::array::from_fn(|k| Dual { circle: duals[k], encloses: encloses(k) }))
+}
+
+/// Invert `z` in the dual circles until no dual captures it.
+///
+/// The accumulated inversion scale turns the distance from the final point to the
+/// nearest starting circle into an estimate of the distance to the gasket; pixels
+/// closer than `pixel_size` return 255, others a value based on the inversion depth.
+fn apollonian_depth(circles: &[Circle; 4], duals: &[Dual; 4], mut z: Complex, pixel_size: f64) -> u32 {
+	let mut depth = 0;
+	let mut scale = 1.0;
+	while depth < 255 {
+		match duals.iter().find(|d| d.captures(z)).map(|d| d.circle) {
+			Some(circle) => {
+				// Inversion in a circle of radius r scales lengths near z by r^2 / |z - center|^2
+				scale *= circle.radius * circle.radius / (z - circle.center).norm_sqr();
+				z = circle.invert(z);
+			}
+			None => break,
+		}
+		depth += 1;
+	}
+	let dist = circles
+		.iter()
+		.map(|c| ((z - c.center).abs() - c.radius).abs())
+		.fold(f64::INFINITY, f64::min);
+	if depth == 255 || dist / scale < pixel_size {
+		255
+	} else {
+		(depth * DEPTH_COLOR_STEP).min(254)
+	}
+}
+
+/// Compute the inversion depth of every pixel for the Apollonian gasket.
+///
+/// * `width`, `height` - Output size in pixels
+/// * `view` - Complex plane region to render
+/// * `params` - Starting circle radii
+///
+/// Returns one value per pixel in 0..=255, where 255 marks the gasket itself, or an
+/// empty field (all 0) if the radii fail `check_apollonian_radii`.
+pub fn apollonian_field(width: usize, height: usize, view: ViewRect, params: &ApollonianParams) -> Vec<u32> {
+	if check_apollonian_radii(params).is_err() {
+		return vec![0; width * height];
+	}
+	let (circles, duals) = apollonian_circles(params);
+	let pixel_size = (view.max_x - view.min_x).abs() / width.max(1) as f64;
+	let mut field = vec![0u32; width * height];
+	for y in 0..height {
+		for x in 0..width {
+			let zx = view.min_x + x as f64 / width as f64 * (view.max_x - view.min_x);
+			let zy = view.min_y + y as f64 / height as f64 * (view.max_y - view.min_y);
+			field[y * width + x] = apollonian_depth(&circles, &duals, Complex::new(zx, zy), pixel_size);
+		}
+	}
+	field
+}
+
+/// A Möbius transformation as a 2x2 complex matrix [[a, b], [c, d]].
+#[derive(Clone, Copy)]
+struct Matrix {
+	a: Complex,
+	b: Complex,
+	c: Complex,
+	d: Complex,
+}
+
+impl Matrix {
+	fn from_mobius(m: &Mobius) -> Matrix {
+		Matrix { a: m[0].into(), b: m[1].into(), c: m[2].into(), d: m[3].into() }
+	}
+
+	fn mul(&self, o: &Matrix) -> Matrix {
+		Matrix {
+			a: self.a * o.a + self.b * o.c,
+			b: self.a * o.b + self.b * o.d,
+			c: self.c * o.a + self.d * o.c,
+			d: self.c * o.b + self.d * o.d,
+		}
+	}
+
+	/// Inverse transformation (the adjugate; the determinant cancels for Möbius maps).
+	fn inverse(&self) -> Matrix {
+		Matrix { a: self.d, b: -self.b, c: -self.c, d: self.a }
+	}
+
+	fn apply(&self, z: Complex) -> Complex {
+		(self.a * z + self.b) / (self.c * z + self.d)
+	}
+
+	/// Attracting fixed point (the one where |cz + d| > 1).
+	fn attracting_fixed_point(&self) -> Complex {
+		if self.c.norm_sqr() < 1e-24 {
+			// Affine map: the finite fixed point is attracting when |a/d| < 1
+			return self.b / (self.d - self.a);
+		}
+		let disc = ((self.a - self.d) * (self.a - self.d) + self.b * self.c * 4.0).sqrt();
+		let two_c = self.c * 2.0;
+		let z1 = (self.a - self.d + disc) / two_c;
+		let z2 = (self.a - self.d - disc) / two_c;
+		if (self.c * z1 + self.d).norm_sqr() >= (self.c * z2 + self.d).norm_sqr() { z1 } else { z2 }
+	}
+}
+
+/// Compute generators a, b for Grandma's recipe from their traces (Indra's Pearls, ch. 6).
+///
+/// Returns `(a, b)` as Möbius coefficients.
+pub fn grandma_recipe(ta: (f64, f64), tb: (f64, f64)) -> (Mobius, Mobius) {
+	let ta = Complex::from(ta);
+	let tb = Complex::from(tb);
+	let i = Complex::new(0.0, 1.0);
+	let two = Complex::new(2.0, 0.0);
+	let four = Complex::new(4.0, 0.0);
+	// tab solves tab^2 - ta*tb*tab + ta^2 + tb^2 = 0
+	let tab = (ta * tb - (ta * ta * tb * tb - (ta * ta + tb * tb) * 4.0).sqrt()) / 2.0;
+	let z0 = (tab - two) * tb / (tb * tab - ta * 2.0 + i * tab * 2.0);
+	let a = [
+		ta / 2.0,
+		(ta * tab - tb * 2.0 + i * 4.0) / ((tab * 2.0 + four) * z0),
+		(ta * tab - tb * 2.0 - i * 4.0) * z0 / (tab * 2.0 - four),
+		ta / 2.0,
+	];
+	let b = [(tb - i * 2.0) / 2.0, tb / 2.0, tb / 2.0, (tb + i * 2.0) / 2.0];
+	(a.map(Into::into), b.map(Into::into))
+}
+
+/// Upper bound on words visited, so generators that do not contract cannot stall the UI.
+const MAX_WORDS: u64 = 20_000_000;
+
+/// State shared by the recursive word enumeration.
+struct WordSearch<'a> {
+	/// Generators in cyclic order a, b, A, B
+	gens: [Matrix; 4],
+	/// For each generator, the fixed points of its repetends (commutator, itself, reverse commutator)
+	fixed: [[Complex; 3]; 4],
+	max_depth: u32,
+	epsilon: f64,
+	/// Words left to visit before the search gives up
+	budget: u64,
+	width: usize,
+	height: usize,
+	view: ViewRect,
+	/// Smallest word length that reached each pixel (255 = never reached)
+	field: &'a mut [u32],
+}
+
+impl WordSearch<'_> {
+	/// Explore all reduced words that start with `word` and end in generator `last`.
+	fn explore(&mut self, word: Matrix, last: usize, depth: u32) {
+		if self.budget == 0 {
+			return;
+		}
+		self.budget -= 1;
+		let points = self.fixed[last].map(|p| word.apply(p));
+		let small = points.windows(2).all(|w| (w[0] - w[1]).abs() < self.epsilon);
+		if depth >= self.max_depth || small {
+			let value = (depth * 254 / self.max_depth.max(1)).min(254);
+			self.plot_segment(points[0], points[1], value);
+			self.plot_segment(points[1], points[2], value);
+			return;
+		}
+		// Children in the order b, a, B (for a), skipping the inverse of the last generator
+		for next in [(last + 1) % 4, last, (last + 3) % 4] {
+			self.explore(word.mul(&self.gens[next]), next, depth + 1);
+		}
+	}
+
+	/// Rasterize a short line segment into the field.
+	fn plot_segment(&mut self, p: Complex, q: Complex, value: u32) {
+		let to_pixel = |z: Complex| {
+			(
+				(z.re - self.view.min_x) / (self.view.max_x - self.view.min_x) * self.width as f64,
+				(z.im - self.view.min_y) / (self.view.max_y - self.view.min_y) * self.height as f64,
+			)
+		};
+		let (x0, y0) = to_pixel(p);
+		let (x1, y1) = to_pixel(q);
+		if !(x0.is_finite() && y0.is_finite() && x1.is_finite() && y1.is_finite()) {
+			return;
+		}
+		let steps = (x1 - x0).abs().max((y1 - y0).abs()).ceil().clamp(1.0, 64.0) as usize;
+		for s in 0..=steps {
+			let t = s as f64 / steps as f64;
+			let (x, y) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+			if x >= 0.0 && y >= 0.0 && (x as usize) < self.width && (y as usize) < self.height {
+				let idx = y as usize * self.width + x as usize;
+				self.field[idx] = self.field[idx].min(value);
+			}
+		}
+	}
+}
+
+/// Plot the limit set of the group generated by `params.a` and `params.b`.
+///
+/// * `width`, `height` - Output size in pixels
+/// * `view` - Complex plane region to render
+/// * `params` - Generators and maximum word length
+///
+/// Returns one value per pixel in 0..=255: the scaled word length that first reached
+/// the pixel, or 255 for pixels away from the limit set.
+pub fn kleinian_field(width: usize, height: usize, view: ViewRect, params: &KleinianParams) -> Vec<u32> {
+	let a = Matrix::from_mobius(&params.a);
+	let b = Matrix::from_mobius(&params.b);
+	let gens = [a, b, a.inverse(), b.inverse()];
+	let fixed = std::array::from_fn(|k| {
+		let g = |offset: usize| gens[(k + offset) % 4];
+		[
+			g(0).mul(&g(1)).mul(&g(2)).mul(&g(3)).attracting_fixed_point(),
+			g(0).attracting_fixed_point(),
+			g(0).mul(&g(3)).mul(&g(2)).mul(&g(1)).attracting_fixed_point(),
+		]
+	});
+	let mut field = vec![255u32; width * height];
+	let mut search = WordSearch {
+		gens,
+		fixed,
+		max_depth: params.max_depth.max(1),
+		epsilon: (view.max_x - view.min_x).abs() / width.max(1) as f64,
+		budget: MAX_WORDS,
+		width,
+		height,
+		view,
+		field: &mut field,
+	};
+	for (k, generator) in gens.iter().enumerate() {
+		search.explore(*generator, k, 1);
+	}
+	field
+}
@@ -0,0 +1,388 @@
+//! Coloring pipeline: turns per-pixel iteration data into RGBA pixels.
+//! The fractal modules produce one `Sample` per pixel; the coloring settings decide
+//! how those samples are laid over the palette.
+
+use crate::types::{ColoringParams, ColorAlgorithm, Histogram, InteriorMode, InteriorParams, TrapParams, AverageParams, DistanceMode, LightingParams, Gradient, RenderSettings, ColorLayer, Palette, DecompositionParams, TextureParams, TextureSource, TextureImage};
+use std::f64::consts::TAU;
+use crate::palette::{palette_color, transfer};
+use crate::colorspace::{blend, from_bytes};
+use crate::texture::sample_texture;
+
+/// Iteration limit of the escape-time fractals; samples at this count are interior.
+pub const MAX_ITER: u32 = 255;
+/// Number of histogram bins over the 0..MAX_ITER value range.
+const HISTOGRAM_BINS: usize = 1024;
+/// Golden-ratio step between the palette positions of successive cycle periods.
+const PERIOD_STEP: f64 = 0.618_034;
+/// Smallest interior convergent sum told apart by the "Exponential smoothing" interior mode.
+const INTERIOR_SUM_FLOOR: f64 = 1e-4;
+/// Color used to draw overlays such as the inverse iteration boundary.
+const OVERLAY_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Iteration data for one pixel.
+#[derive(Clone, Copy)]
+pub struct Sample {
+	/// Whole iterations before escape (MAX_ITER for interior points)
+	pub iterations: u32,
+	/// Continuous iteration count in 0..MAX_ITER (equal to `iterations` for fields without one)
+	pub smooth: f32,
+	/// Drawn in the overlay color instead of the palette
+	pub overlay: bool,
+	/// Final z (interior points of escape-time fractals)
+	pub z: [f32; 2],
+	/// Period of the attracting cycle (0 if none was found)
+	pub period: u16,
+	/// Magnitude of the attracting cycle's multiplier
+	pub multiplier: f32,
+	/// Closest approach of the orbit to the orbit trap
+	pub trap_distance: f32,
+	/// Iteration at which that closest approach happened
+	pub trap_iteration: u16,
+	/// Offset of that closest approach from the trap center, in the trap's frame
+	pub trap_point: [f32; 2],
+	/// Stripe, triangle inequality and curvature averages over the orbit (0.0 to 1.0)
+	pub averages: [f32; 3],
+	/// Exterior distance estimate to the set in pixels (infinite where not available)
+	pub distance: f32,
+	/// Unit direction away from the set, from the distance estimate ([0, 0] where not available)
+	pub normal: [f32; 2],
+	/// Argument of z at escape in radians (escape-time fractals)
+	pub angle: f32,
+	/// Exponential smoothing sums over the orbit: exp(-|z|) and exp(-1/|z_n - z_n-1|)
+	pub exponential: [f32; 2],
+}
+
+impl Default for Sample {
+	fn default() -> Self {
+		Self {
+			iterations: 0,
+			smooth: 0.0,
+			overlay: false,
+			z: [0.0; 2],
+			period: 0,
+			multiplier: 0.0,
+			trap_distance: 0.0,
+			trap_iteration: 0,
+			trap_point: [0.0; 2],
+			averages: [0.0; 3],
+			distance: f32::INFINITY,
+			normal: [0.0; 2],
+			angle: 0.0,
+			exponential: [0.0; 2],
+		}
+	}
+}
+
+impl Sample {
+	/// A sample from a field of whole values in 0..=255, where 255 means interior.
+	pub fn from_value(value: u32) -> Self {
+		Self { iterations: value, smooth: value as f32, ..Self::default() }
+	}
+
+	/// A sample from a continuous value in 0.0..MAX_ITER (never interior).
+	pub fn from_smooth(value: f64) -> Self {
+		let smooth = value.clamp(0.0, MAX_ITER as f64 - 1e-3);
+		Self { iterations: smooth as u32, smooth: smooth as f32, ..Self::default() }
+	}
+
+	/// A point that escaped radius 2 after `iterations` steps and reached |z|² = `norm_sqr`
+	/// after `extra` further steps of a formula of degree `power`.
+	///
+	/// The smooth count is the normalized iteration count, which matches `iterations`
+	/// at the moment of escape and varies continuously across iteration bands.
+	pub fn escaped(iterations: u32, extra: u32, norm_sqr: f64, power: f64) -> Self {
+		let log_radius = 0.5 * norm_sqr.ln() / std::f64::consts::LN_2;
+		let smooth = (iterations + extra) as f64 - log_radius.ln() / power.max(1.01).ln();
+		let smooth = if smooth.is_finite() { smooth.clamp(0.0, MAX_ITER as f64 - 1e-3) } else { iterations as f64 };
+		Self { iterations, smooth: smooth as f32, ..Self::default() }
+	}
+
+	/// A point that never escaped, ending at `z` on an attracting cycle of `period`
+	/// (0 if unknown) with multiplier magnitude `multiplier`.
+	pub fn interior(z: (f64, f64), period: u16, multiplier: f64) -> Self {
+		Self {
+			iterations: MAX_ITER,
+			smooth: MAX_ITER as f32,
+			z: [z.0 as f32, z.1 as f32],
+			period,
+			multiplier: multiplier as f32,
+			..Self::default()
+		}
+	}
+
+	/// Is this point inside the set (never escaped)?
+	pub fn is_interior(&self) -> bool {
+		self.iterations >= MAX_ITER
+	}
+
+	/// The value mapped onto the palette: smooth or whole iterations.
+	fn value(&self, smooth: bool) -> f64 {
+		if smooth { self.smooth as f64 } else { self.iterations as f64 }
+	}
+}
+
+/// Build the cumulative distribution of exterior sample values.
+///
+/// * `smooth` - Count smooth values instead of whole iterations
+pub fn build_histogram(field: &[Sample], smooth: bool) -> Histogram {
+	let mut counts = Vec::new();
+	count_histogram(field, smooth, &mut counts);
+	histogram_from_counts(&counts)
+}
+
+/// Add the exterior sample values of `field` to histogram bin counts, so a histogram can
+/// be built over several parts of an image.
+///
+/// * `counts` - Bin counts so far (an empty vector starts a new count)
+pub fn count_histogram(field: &[Sample], smooth: bool, counts: &mut Vec<u64>) {
+	counts.resize(HISTOGRAM_BINS, 0);
+	for s in field.iter().filter(|s| !s.is_interior()) {
+		let bin = (s.value(smooth) / MAX_ITER as f64 * HISTOGRAM_BINS as f64) as usize;
+		counts[bin.min(HISTOGRAM_BINS - 1)] += 1;
+	}
+}
+
+/// Build the cumulative distribution from histogram bin counts.
+pub fn histogram_from_counts(counts: &[u64]) -> Histogram {
+	let total = counts.iter().sum::<u64>().max(1) as f64;
+	// cdf[k] is the fraction of samples in bins 0..=k
+	let mut running = 0;
+	let cdf = counts
+		.iter()
+		.map(|&n| {
+			running += n;
+			(running as f64 / total) as f32
+		})
+		.collect();
+	Histogram { cdf }
+}
+
+/// Palette position (0.0 to 1.0) of a value under a histogram, interpolated within its bin.
+fn histogram_position(histogram: &Histogram, value: f64) -> f64 {
+	let cdf = &histogram.cdf;
+	if cdf.is_empty() {
+		return value / MAX_ITER as f64;
+	}
+	let x = (value / MAX_ITER as f64 * cdf.len() as f64).clamp(0.0, cdf.len() as f64 - 1e-9);
+	let bin = x as usize;
+	let below = if bin == 0 { 0.0 } else { cdf[bin - 1] as f64 };
+	below + (cdf[bin] as f64 - below) * x.fract()
+}
+
+/// Palette position of a sample's orbit trap distance; the palette spans the trap size.
+fn trap_position(s: &Sample, trap: &TrapParams) -> f64 {
+	(s.trap_distance as f64 / trap.size.max(1e-12)).min(1.0)
+}
+
+/// Palette position of one of a sample's orbit averages, blended with its smooth count.
+fn average_position(s: &Sample, k: usize, averages: &AverageParams) -> f64 {
+	let blend = averages.blend.clamp(0.0, 1.0);
+	s.averages[k] as f64 * (1.0 - blend) + s.smooth as f64 / MAX_ITER as f64 * blend
+}
+
+/// Palette position of a sample's escape angle for the decomposition algorithms.
+fn angle_position(s: &Sample, algorithm: ColorAlgorithm, decomposition: &DecompositionParams) -> f64 {
+	let turn = (s.angle as f64 / TAU).rem_euclid(1.0);
+	match algorithm {
+		ColorAlgorithm::BinaryDecomposition => if s.angle < 0.0 { 1.0 } else { 0.0 },
+		ColorAlgorithm::AngleSectors => {
+			let n = decomposition.sectors.max(2) as f64;
+			(turn * n).floor().min(n - 1.0) / (n - 1.0)
+		}
+		_ => turn,
+	}
+}
+
+/// Color an interior sample by the interior mode, through the interior palette.
+fn interior_color(s: &Sample, interior: &InteriorParams, trap: &TrapParams, gradient: &Gradient) -> [f32; 3] {
+	let [re, im] = s.z.map(|v| v as f64);
+	let t = match interior.mode {
+		InteriorMode::Solid => None,
+		// Interior orbits stay within radius 2
+		InteriorMode::Magnitude => Some(re.hypot(im) / 2.0),
+		InteriorMode::Angle => Some((im.atan2(re) / TAU).rem_euclid(1.0)),
+		InteriorMode::Period if s.period > 0 => Some((s.period as f64 * PERIOD_STEP).fract()),
+		InteriorMode::Multiplier if s.period > 0 => Some(s.multiplier as f64),
+		InteriorMode::Period | InteriorMode::Multiplier => None,
+		InteriorMode::OrbitTrap => Some(trap_position(s, trap)),
+		// The sum spans several decades, from tiny deep in a basin to near MAX_ITER where
+		// the orbit converges slowly, so it is spread on a log scale over that range
+		InteriorMode::ExponentialSmoothing => {
+			let sum = (s.exponential[1] as f64).max(INTERIOR_SUM_FLOOR);
+			Some((sum / INTERIOR_SUM_FLOOR).ln() / (MAX_ITER as f64 / INTERIOR_SUM_FLOOR).ln())
+		}
+	};
+	match t {
+		Some(t) => palette_color(t, interior.palette, gradient),
+		None => from_bytes([interior.color.0, interior.color.1, interior.color.2]),
+	}
+}
+
+/// Surface normal of the relief at pixel `idx`.
+///
+/// Uses the distance-estimate direction when the sample has one; otherwise the gradient
+/// of the smooth iteration count across neighbouring exterior pixels.
+fn surface_normal(field: &[Sample], width: usize, idx: usize, height: f64) -> [f64; 3] {
+	let s = &field[idx];
+	let (nx, ny) = if s.normal != [0.0; 2] {
+		(s.normal[0] as f64 * height, s.normal[1] as f64 * height)
+	} else {
+		// Interior and missing neighbours count as level with this pixel
+		let value = |i: Option<usize>| match i.and_then(|i| field.get(i)) {
+			Some(n) if !n.is_interior() => n.smooth as f64,
+			_ => s.smooth as f64,
+		};
+		let (x, y) = (idx % width, idx / width);
+		let left = value((x > 0).then(|| idx - 1));
+		let right = value((x + 1 < width).then_some(idx + 1));
+		let up = value((y > 0).then(|| idx - width));
+		let down = value(Some(idx + width));
+		// The smooth count rises towards the set, so the surface faces away from it
+		(-(right - left) / 2.0 * height, -(down - up) / 2.0 * height)
+	};
+	let len = (nx * nx + ny * ny + 1.0).sqrt();
+	[nx / len, ny / len, 1.0 / len]
+}
+
+/// Light a color with Blinn-Phong shading of surface `normal`.
+fn light(color: [f32; 3], normal: [f64; 3], lighting: &LightingParams) -> [f32; 3] {
+	let (az, el) = (lighting.azimuth.to_radians(), lighting.elevation.to_radians());
+	let l = [el.cos() * az.cos(), el.cos() * az.sin(), el.sin()];
+	let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+	let diffuse = dot(normal, l).max(0.0);
+	// Halfway vector between the light and a viewer looking straight down
+	let h = [l[0], l[1], l[2] + 1.0];
+	let h_len = dot(h, h).sqrt().max(1e-12);
+	let specular = (dot(normal, h) / h_len).max(0.0).powf(lighting.shininess);
+	let brightness = lighting.ambient + lighting.diffuse * diffuse;
+	let highlight = lighting.specular * specular;
+	color.map(|v| (v as f64 * brightness + highlight).clamp(0.0, 1.0) as f32)
+}
+
+/// Palette position (0.0 to 1.0) of an exterior sample under a coloring algorithm.
+fn exterior_position(s: &Sample, algorithm: ColorAlgorithm, smooth: bool, histogram: Option<&Histogram>, settings: &RenderSettings) -> f64 {
+	match algorithm {
+		ColorAlgorithm::Iteration => {
+			let value = s.value(smooth);
+			match histogram {
+				Some(h) => histogram_position(h, value),
+				None => value / MAX_ITER as f64,
+			}
+		}
+		ColorAlgorithm::TrapDistance => trap_position(s, &settings.trap),
+		ColorAlgorithm::TrapIteration => s.trap_iteration as f64 / MAX_ITER as f64,
+		ColorAlgorithm::StripeAverage => average_position(s, 0, &settings.averages),
+		ColorAlgorithm::TriangleInequality => average_position(s, 1, &settings.averages),
+		ColorAlgorithm::CurvatureAverage => average_position(s, 2, &settings.averages),
+		ColorAlgorithm::BinaryDecomposition | ColorAlgorithm::AngleSectors | ColorAlgorithm::ExternalAngle => {
+			angle_position(s, algorithm, &settings.coloring.decomposition)
+		}
+		// Both sums grow by at most one per iteration, so they share the iteration scale
+		ColorAlgorithm::ExponentialSmoothing => s.exponential[0] as f64 / MAX_ITER as f64,
+		ColorAlgorithm::ConvergentSmoothing => s.exponential[1] as f64 / MAX_ITER as f64,
+	}
+}
+
+/// Composite the enabled color layers over an exterior color, bottom to top.
+fn apply_layers(mut color: [f32; 3], s: &Sample, layers: &[ColorLayer], smooth: bool, settings: &RenderSettings) -> [f32; 3] {
+	for layer in layers.iter().filter(|l| l.enabled) {
+		let t = transfer(exterior_position(s, layer.algorithm, smooth, None, settings), &layer.transfer);
+		let top = match &layer.custom {
+			Some(named) => palette_color(t, Palette::UserDefined, &named.gradient),
+			None => palette_color(t, layer.palette, &settings.gradient),
+		};
+		color = blend(color, top, layer.blend, layer.opacity);
+	}
+	color
+}
+
+/// Composite the texture over a color, looked up at the sample's texture coordinates.
+fn apply_texture(color: [f32; 3], s: &Sample, params: &TextureParams, texture: &TextureImage) -> [f32; 3] {
+	let (x, y) = match params.source {
+		TextureSource::FinalZ if s.is_interior() => (s.z[0] as f64, s.z[1] as f64),
+		// Escaped z is unbounded, so use its angle and the position within the iteration band
+		TextureSource::FinalZ => ((s.angle as f64 / TAU).rem_euclid(1.0), (s.smooth as f64).fract()),
+		TextureSource::OrbitTrap => (s.trap_point[0] as f64, s.trap_point[1] as f64),
+	};
+	let u = x * params.scale + params.offset.0;
+	let v = y * params.scale + params.offset.1;
+	blend(color, sample_texture(texture, u, v, params.wrap), params.blend, params.opacity)
+}
+
+/// Color a field of samples with the palette and coloring settings.
+///
+/// * `width` - Image width in pixels (the field is row-major)
+///
+/// Returns a flat RGBA buffer (row-major order) of float channels, 0.0 to 1.0.
+/// Alpha is 0.0 for points made transparent and 1.0 everywhere else.
+pub fn colorize(field: &[Sample], width: usize, settings: &RenderSettings) -> Vec<f32> {
+	let params: &ColoringParams = &settings.coloring;
+	let histogram = match (params.uses_histogram(), &params.frozen_histogram) {
+		(false, _) => None,
+		(true, Some(frozen)) => Some(frozen.clone()),
+		(true, None) => Some(build_histogram(field, params.smooth)),
+	};
+	let distance = &params.distance;
+	let thickness = distance.thickness.max(1e-3) as f32;
+	let transparency = &params.transparency;
+	let texture = settings.texture.as_deref().filter(|_| params.texture.enabled);
+	let mut pixels = vec![0.0f32; field.len() * 4];
+	for (idx, s) in field.iter().enumerate() {
+		let near_boundary = s.distance < thickness;
+		let color = if s.overlay {
+			OVERLAY_COLOR
+		} else if distance.mode == DistanceMode::Lines && near_boundary {
+			from_bytes([distance.color.0, distance.color.1, distance.color.2])
+		} else if s.is_interior() || (distance.mode == DistanceMode::Fill && near_boundary) {
+			let color = interior_color(s, &params.interior, &settings.trap, &settings.gradient);
+			match texture {
+				Some(image) if params.texture.interior && s.is_interior() => apply_texture(color, s, &params.texture, image),
+				_ => color,
+			}
+		} else {
+			let mut t = transfer(exterior_position(s, params.algorithm, params.smooth, histogram.as_ref(), settings), &params.transfer);
+			if params.offset != 0.0 {
+				t = (t + params.offset).rem_euclid(1.0);
+			}
+			let mut color = palette_color(t, settings.palette, &settings.gradient);
+			color = apply_layers(color, s, &params.layers, params.smooth, settings);
+			if let Some(image) = texture {
+				color = apply_texture(color, s, &params.texture, image);
+			}
+			if distance.mode == DistanceMode::Shading {
+				// Fade to black over the falloff distance; the fourth root keeps filaments bright
+				let shade = (s.distance / thickness).clamp(0.0, 1.0).powf(0.25);
+				color = color.map(|v| v * shade);
+			}
+			if params.lighting.enabled {
+				color = light(color, surface_normal(field, width, idx, params.lighting.height), &params.lighting);
+			}
+			color
+		};
+		let transparent = !s.overlay
+			&& if s.is_interior() {
+				transparency.interior
+			} else {
+				let value = s.value(params.smooth);
+				transparency.range && value >= transparency.min && value <= transparency.max
+			};
+		pixels[idx * 4..idx * 4 + 3].copy_from_slice(&color);
+		pixels[idx * 4 + 3] = if transparent { 0.0 } else { 1.0 };
+	}
+	pixels
+}
+
+/// Quantize a float RGBA buffer to 8 bits per channel for display.
+pub fn to_rgba8(pixels: &[f32]) -> Vec<u8> {
+	pixels.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+}
+
+/// Quantize a float RGBA buffer to 16 bits per channel for export.
+pub fn to_rgba16(pixels: &[f32]) -> Vec<u16> {
+	pixels.iter().map(|v| (v.clamp(0.0, 1.0) * 65535.0).round() as u16).collect()
+}
+
+/// Drop the alpha channel of an RGBA buffer.
+pub fn strip_alpha<T: Copy>(pixels: &[T]) -> Vec<T> {
+	pixels.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect()
+}
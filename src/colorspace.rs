@@ -0,0 +1,260 @@
+//! Color space conversions used to blend gradient stops.
+//! Covers linear RGB, HSV, CIE Lab/LCh (D65 white) and OKLab/OKLCh,
+//! plus the blend modes used to composite color layers.
+//! Results are sRGB-encoded float colors with channels 0.0 to 1.0, so nothing is
+//! quantized until the image is displayed or saved.
+
+use crate::types::{BlendMode, ColorSpace};
+use std::f64::consts::TAU;
+
+/// Below this chroma (or saturation) a color's hue is meaningless and is ignored.
+const ACHROMATIC: f64 = 1e-4;
+
+/// D65 reference white in XYZ.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+/// Quantize a float color (channels 0.0 to 1.0) to bytes.
+pub fn to_bytes(color: [f32; 3]) -> [u8; 3] {
+	color.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+/// Expand a byte color to a float color (channels 0.0 to 1.0).
+pub fn from_bytes(color: [u8; 3]) -> [f32; 3] {
+	color.map(|v| v as f32 / 255.0)
+}
+
+/// Decode an sRGB byte to linear light (0.0 to 1.0).
+fn to_linear(c: u8) -> f64 {
+	let c = c as f64 / 255.0;
+	if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Encode linear light as an sRGB value (0.0 to 1.0), clamping out-of-gamut values.
+fn from_linear(c: f64) -> f32 {
+	let c = c.clamp(0.0, 1.0);
+	let s = if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+	s as f32
+}
+
+/// Decode an sRGB color to linear light.
+fn linear_rgb(c: [u8; 3]) -> [f64; 3] {
+	c.map(to_linear)
+}
+
+/// Encode a linear-light color as sRGB.
+fn from_linear_rgb(c: [f64; 3]) -> [f32; 3] {
+	c.map(from_linear)
+}
+
+/// sRGB to (hue in radians, saturation, value).
+fn rgb_to_hsv(c: [u8; 3]) -> [f64; 3] {
+	let [r, g, b] = c.map(|v| v as f64 / 255.0);
+	let max = r.max(g).max(b);
+	let min = r.min(g).min(b);
+	let d = max - min;
+	let h = if d == 0.0 {
+		0.0
+	} else if max == r {
+		((g - b) / d).rem_euclid(6.0)
+	} else if max == g {
+		(b - r) / d + 2.0
+	} else {
+		(r - g) / d + 4.0
+	};
+	let s = if max == 0.0 { 0.0 } else { d / max };
+	[h / 6.0 * TAU, s, max]
+}
+
+/// (hue in radians, saturation, value) to sRGB.
+fn hsv_to_rgb([h, s, v]: [f64; 3]) -> [f32; 3] {
+	let h = h.rem_euclid(TAU) / TAU * 6.0;
+	let c = v * s;
+	let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+	let (r, g, b) = match h as u32 {
+		0 => (c, x, 0.0),
+		1 => (x, c, 0.0),
+		2 => (0.0, c, x),
+		3 => (0.0, x, c),
+		4 => (x, 0.0, c),
+		_ => (c, 0.0, x),
+	};
+	let m = v - c;
+	[r, g, b].map(|k| (k + m).clamp(0.0, 1.0) as f32)
+}
+
+/// sRGB to CIE L*a*b*, via XYZ.
+fn rgb_to_lab(c: [u8; 3]) -> [f64; 3] {
+	let [r, g, b] = linear_rgb(c);
+	let xyz = [
+		0.4124564 * r + 0.3575761 * g + 0.1804375 * b,
+		0.2126729 * r + 0.7151522 * g + 0.0721750 * b,
+		0.0193339 * r + 0.1191920 * g + 0.9503041 * b,
+	];
+	let f = |t: f64| if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 };
+	let [fx, fy, fz] = [0, 1, 2].map(|k| f(xyz[k] / WHITE[k]));
+	[116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE L*a*b* to sRGB, via XYZ.
+fn lab_to_rgb([l, a, b]: [f64; 3]) -> [f32; 3] {
+	let fy = (l + 16.0) / 116.0;
+	let fx = fy + a / 500.0;
+	let fz = fy - b / 200.0;
+	let finv = |f: f64| if f.powi(3) > 216.0 / 24389.0 { f.powi(3) } else { (116.0 * f - 16.0) * 27.0 / 24389.0 };
+	let [x, y, z] = [fx, fy, fz].map(finv);
+	let [x, y, z] = [x * WHITE[0], y * WHITE[1], z * WHITE[2]];
+	from_linear_rgb([
+		3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+		-0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+		0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+	])
+}
+
+/// sRGB to OKLab.
+fn rgb_to_oklab(c: [u8; 3]) -> [f64; 3] {
+	let [r, g, b] = linear_rgb(c);
+	let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+	let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+	let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+	[
+		0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+		1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+		0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+	]
+}
+
+/// OKLab to sRGB.
+fn oklab_to_rgb([l, a, b]: [f64; 3]) -> [f32; 3] {
+	let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+	let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+	let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+	from_linear_rgb([
+		4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+		-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+		-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+	])
+}
+
+/// Cartesian (L, a, b) to polar (L, C, h).
+fn to_polar([l, a, b]: [f64; 3]) -> [f64; 3] {
+	[l, a.hypot(b), b.atan2(a)]
+}
+
+/// Polar (L, C, h) to cartesian (L, a, b).
+fn from_polar([l, c, h]: [f64; 3]) -> [f64; 3] {
+	[l, c * h.cos(), c * h.sin()]
+}
+
+/// Linear blend of two numbers.
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+	a + (b - a) * t
+}
+
+/// Channel-wise linear blend of two triples.
+fn lerp3(a: [f64; 3], b: [f64; 3], t: f64) -> [f64; 3] {
+	[0, 1, 2].map(|k| lerp(a[k], b[k], t))
+}
+
+/// Blend two colors whose hue (radians) is in channel `hue` and chroma or saturation
+/// in channel `chroma`, taking the shorter way around the hue circle. A gray end
+/// borrows the other end's hue so the blend does not swing through unrelated colors.
+fn lerp_hue(mut a: [f64; 3], mut b: [f64; 3], t: f64, chroma: usize, hue: usize) -> [f64; 3] {
+	if a[chroma] < ACHROMATIC {
+		a[hue] = b[hue];
+	} else if b[chroma] < ACHROMATIC {
+		b[hue] = a[hue];
+	}
+	let mut d = (b[hue] - a[hue]).rem_euclid(TAU);
+	if d > TAU / 2.0 {
+		d -= TAU;
+	}
+	let mut out = lerp3(a, b, t);
+	out[hue] = a[hue] + d * t;
+	out
+}
+
+/// OKLCh (lightness, chroma, hue in radians) to sRGB, clipped to the gamut.
+pub fn from_oklch(lch: [f64; 3]) -> [f32; 3] {
+	oklab_to_rgb(from_polar(lch))
+}
+
+/// Blend colors `a` and `b` by `t` (0.0 gives `a`, 1.0 gives `b`) in the given color space.
+pub fn mix(a: [u8; 3], b: [u8; 3], t: f64, space: ColorSpace) -> [f32; 3] {
+	match space {
+		ColorSpace::Srgb => [0, 1, 2].map(|k| (lerp(a[k] as f64, b[k] as f64, t) / 255.0) as f32),
+		ColorSpace::LinearRgb => from_linear_rgb(lerp3(linear_rgb(a), linear_rgb(b), t)),
+		ColorSpace::Hsv => {
+			// Hue in channel 0, saturation in channel 1
+			hsv_to_rgb(lerp_hue(rgb_to_hsv(a), rgb_to_hsv(b), t, 1, 0))
+		}
+		ColorSpace::Lab => lab_to_rgb(lerp3(rgb_to_lab(a), rgb_to_lab(b), t)),
+		ColorSpace::Lch => {
+			let lch = lerp_hue(to_polar(rgb_to_lab(a)), to_polar(rgb_to_lab(b)), t, 1, 2);
+			lab_to_rgb(from_polar(lch))
+		}
+		ColorSpace::Oklab => oklab_to_rgb(lerp3(rgb_to_oklab(a), rgb_to_oklab(b), t)),
+		ColorSpace::Oklch => {
+			let lch = lerp_hue(to_polar(rgb_to_oklab(a)), to_polar(rgb_to_oklab(b)), t, 1, 2);
+			oklab_to_rgb(from_polar(lch))
+		}
+	}
+}
+
+/// Luminosity used by the non-separable blend modes (W3C compositing spec).
+fn luminosity([r, g, b]: [f64; 3]) -> f64 {
+	0.3 * r + 0.59 * g + 0.11 * b
+}
+
+/// Shift a color to luminosity `l`, clipping back into gamut while keeping its hue.
+fn set_luminosity(c: [f64; 3], l: f64) -> [f64; 3] {
+	let d = l - luminosity(c);
+	let c = c.map(|v| v + d);
+	let l = luminosity(c);
+	let min = c[0].min(c[1]).min(c[2]);
+	let max = c[0].max(c[1]).max(c[2]);
+	c.map(|v| {
+		let v = if min < 0.0 { l + (v - l) * l / (l - min) } else { v };
+		if max > 1.0 { l + (v - l) * (1.0 - l) / (max - l) } else { v }
+	})
+}
+
+/// Give a color saturation (max - min) `s`, keeping the order of its channels.
+fn set_saturation(c: [f64; 3], s: f64) -> [f64; 3] {
+	let min = c[0].min(c[1]).min(c[2]);
+	let max = c[0].max(c[1]).max(c[2]);
+	if max > min { c.map(|v| (v - min) * s / (max - min)) } else { [0.0; 3] }
+}
+
+/// Separable blend of one channel (0.0 to 1.0) of `layer` onto `base`.
+fn blend_channel(base: f64, layer: f64, mode: BlendMode) -> f64 {
+	match mode {
+		BlendMode::Multiply => base * layer,
+		BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - layer),
+		BlendMode::Overlay => {
+			if base < 0.5 { 2.0 * base * layer } else { 1.0 - 2.0 * (1.0 - base) * (1.0 - layer) }
+		}
+		BlendMode::SoftLight => {
+			if layer <= 0.5 {
+				base - (1.0 - 2.0 * layer) * base * (1.0 - base)
+			} else {
+				let d = if base <= 0.25 { ((16.0 * base - 12.0) * base + 4.0) * base } else { base.sqrt() };
+				base + (2.0 * layer - 1.0) * (d - base)
+			}
+		}
+		BlendMode::Normal | BlendMode::Hue => layer,
+	}
+}
+
+/// Composite `layer` over `base` with a blend mode and opacity (0.0 to 1.0).
+pub fn blend(base: [f32; 3], layer: [f32; 3], mode: BlendMode, opacity: f64) -> [f32; 3] {
+	let b = base.map(|v| v as f64);
+	let l = layer.map(|v| v as f64);
+	let blended = match mode {
+		BlendMode::Hue => {
+			let saturation = b[0].max(b[1]).max(b[2]) - b[0].min(b[1]).min(b[2]);
+			set_luminosity(set_saturation(l, saturation), luminosity(b))
+		}
+		_ => [0, 1, 2].map(|k| blend_channel(b[k], l[k], mode)),
+	};
+	[0, 1, 2].map(|k| lerp(b[k], blended[k], opacity.clamp(0.0, 1.0)).clamp(0.0, 1.0) as f32)
+}
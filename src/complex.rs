@@ -0,0 +1,129 @@
+//! Minimal complex number type for the fractal formulas.
+//! Only the operations the renderers need are implemented.
+
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+/// A complex number `re + im*i`.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Complex {
+	/// Real part
+	pub re: f64,
+	/// Imaginary part
+	pub im: f64,
+}
+
+impl Complex {
+	/// Build a complex number from its real and imaginary parts.
+	pub const fn new(re: f64, im: f64) -> Self {
+		Self { re, im }
+	}
+
+	/// Squared magnitude |z|^2 (cheaper than `abs`).
+	pub fn norm_sqr(self) -> f64 {
+		self.re * self.re + self.im * self.im
+	}
+
+	/// Magnitude |z|.
+	pub fn abs(self) -> f64 {
+		self.re.hypot(self.im)
+	}
+
+	/// Argument (angle from the positive real axis) in radians, in (-pi, pi].
+	pub fn arg(self) -> f64 {
+		self.im.atan2(self.re)
+	}
+
+	/// Complex conjugate.
+	pub fn conj(self) -> Self {
+		Self::new(self.re, -self.im)
+	}
+
+	/// Raise to a real power. Whole-number powers use repeated multiplication
+	/// (exact and fast); other powers use the principal branch in polar form.
+	pub fn powf(self, p: f64) -> Self {
+		if p.fract() == 0.0 && (1.0..=16.0).contains(&p) {
+			let mut result = self;
+			for _ in 1..p as u32 {
+				result = result * self;
+			}
+			return result;
+		}
+		let r = self.norm_sqr();
+		if r == 0.0 {
+			return Self::default();
+		}
+		let mag = r.powf(p / 2.0);
+		let arg = self.im.atan2(self.re) * p;
+		Self::new(mag * arg.cos(), mag * arg.sin())
+	}
+
+	/// Principal square root.
+	pub fn sqrt(self) -> Self {
+		let r = self.abs();
+		let re = ((r + self.re) / 2.0).max(0.0).sqrt();
+		let im = ((r - self.re) / 2.0).max(0.0).sqrt();
+		Self::new(re, if self.im < 0.0 { -im } else { im })
+	}
+}
+
+impl From<(f64, f64)> for Complex {
+	fn from((re, im): (f64, f64)) -> Self {
+		Self::new(re, im)
+	}
+}
+
+impl From<Complex> for (f64, f64) {
+	fn from(z: Complex) -> Self {
+		(z.re, z.im)
+	}
+}
+
+impl Add for Complex {
+	type Output = Self;
+	fn add(self, o: Self) -> Self {
+		Self::new(self.re + o.re, self.im + o.im)
+	}
+}
+
+impl Sub for Complex {
+	type Output = Self;
+	fn sub(self, o: Self) -> Self {
+		Self::new(self.re - o.re, self.im - o.im)
+	}
+}
+
+impl Mul for Complex {
+	type Output = Self;
+	fn mul(self, o: Self) -> Self {
+		Self::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+	}
+}
+
+impl Mul<f64> for Complex {
+	type Output = Self;
+	fn mul(self, k: f64) -> Self {
+		Self::new(self.re * k, self.im * k)
+	}
+}
+
+impl Div for Complex {
+	type Output = Self;
+	fn div(self, o: Self) -> Self {
+		let d = o.norm_sqr();
+		Self::new((self.re * o.re + self.im * o.im) / d, (self.im * o.re - self.re * o.im) / d)
+	}
+}
+
+impl Div<f64> for Complex {
+	type Output = Self;
+	fn div(self, k: f64) -> Self {
+		Self::new(self.re / k, self.im / k)
+	}
+}
+
+impl Neg for Complex {
+	type Output = Self;
+	fn neg(self) -> Self {
+		Self::new(-self.re, -self.im)
+	}
+}
@@ -0,0 +1,135 @@
+//! Derive a multi-stop gradient from a reference image by clustering its colors.
+
+use crate::types::{ColorStop, ExtractMethod, Gradient, StopOrder};
+
+/// Images are shrunk to at most this many pixels on a side before clustering.
+const SAMPLE_SIZE: u32 = 128;
+
+/// Lloyd iterations run by k-means.
+const KMEANS_ITERATIONS: usize = 12;
+
+/// Saturation below which a color counts as gray when ordering by hue.
+const GRAY_SATURATION: f64 = 0.1;
+
+/// Mean color of a set of pixels.
+fn mean(pixels: &[[f64; 3]]) -> [f64; 3] {
+	let mut sum = [0.0; 3];
+	for p in pixels {
+		for k in 0..3 {
+			sum[k] += p[k];
+		}
+	}
+	sum.map(|v| v / pixels.len().max(1) as f64)
+}
+
+/// Channel with the widest range in a set of pixels, and that range.
+fn widest_channel(pixels: &[[f64; 3]]) -> (usize, f64) {
+	(0..3)
+		.map(|k| {
+			let min = pixels.iter().map(|p| p[k]).fold(f64::INFINITY, f64::min);
+			let max = pixels.iter().map(|p| p[k]).fold(f64::NEG_INFINITY, f64::max);
+			(k, max - min)
+		})
+		.max_by(|a, b| a.1.total_cmp(&b.1))
+		.unwrap_or((0, 0.0))
+}
+
+/// Median cut: split the box with the widest channel range at its median until there are `count` boxes.
+fn median_cut(pixels: Vec<[f64; 3]>, count: usize) -> Vec<[f64; 3]> {
+	let mut boxes = vec![pixels];
+	while boxes.len() < count {
+		let Some((index, (channel, range))) = boxes
+			.iter()
+			.enumerate()
+			.filter(|(_, b)| b.len() > 1)
+			.map(|(i, b)| (i, widest_channel(b)))
+			.max_by(|a, b| a.1.1.total_cmp(&b.1.1))
+		else {
+			break;
+		};
+		if range <= 0.0 {
+			break;
+		}
+		let mut b = boxes.swap_remove(index);
+		b.sort_by(|p, q| p[channel].total_cmp(&q[channel]));
+		let upper = b.split_off(b.len() / 2);
+		boxes.push(b);
+		boxes.push(upper);
+	}
+	boxes.iter().map(|b| mean(b)).collect()
+}
+
+/// Squared distance between two colors.
+fn distance_sqr(a: [f64; 3], b: [f64; 3]) -> f64 {
+	(0..3).map(|k| (a[k] - b[k]).powi(2)).sum()
+}
+
+/// K-means clustering, starting from the median-cut colors so the result is deterministic.
+fn kmeans(pixels: Vec<[f64; 3]>, count: usize) -> Vec<[f64; 3]> {
+	let mut centers = median_cut(pixels.clone(), count);
+	for _ in 0..KMEANS_ITERATIONS {
+		let mut clusters = vec![Vec::new(); centers.len()];
+		for p in &pixels {
+			let nearest = (0..centers.len())
+				.min_by(|&a, &b| distance_sqr(*p, centers[a]).total_cmp(&distance_sqr(*p, centers[b])))
+				.unwrap_or(0);
+			clusters[nearest].push(*p);
+		}
+		// Empty clusters keep their old center
+		for (center, cluster) in centers.iter_mut().zip(&clusters) {
+			if !cluster.is_empty() {
+				*center = mean(cluster);
+			}
+		}
+	}
+	centers
+}
+
+/// Relative luminance (Rec. 709 weights) of a 0-255 color.
+fn luminance(c: [f64; 3]) -> f64 {
+	0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]
+}
+
+/// Hue angle (0.0 to 6.0, HSV sextants) of a 0-255 color; grays sort first.
+fn hue(c: [f64; 3]) -> f64 {
+	let max = c[0].max(c[1]).max(c[2]);
+	let d = max - c[0].min(c[1]).min(c[2]);
+	if d <= GRAY_SATURATION * max || d == 0.0 {
+		-1.0
+	} else if max == c[0] {
+		((c[1] - c[2]) / d).rem_euclid(6.0)
+	} else if max == c[1] {
+		(c[2] - c[0]) / d + 2.0
+	} else {
+		(c[0] - c[1]) / d + 4.0
+	}
+}
+
+/// Load a PNG or JPEG and derive a gradient of `count` evenly spaced stops from its colors.
+pub fn extract_gradient(path: &str, count: usize, method: ExtractMethod, order: StopOrder) -> Result<Gradient, String> {
+	let img = image::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+	let img = img.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+	let pixels: Vec<[f64; 3]> = img.pixels().map(|p| p.0.map(|v| v as f64)).collect();
+	if pixels.is_empty() {
+		return Err(format!("{} has no pixels", path));
+	}
+	let count = count.max(2);
+	let mut colors = match method {
+		ExtractMethod::MedianCut => median_cut(pixels, count),
+		ExtractMethod::KMeans => kmeans(pixels, count),
+	};
+	match order {
+		StopOrder::Luminance => colors.sort_by(|a, b| luminance(*a).total_cmp(&luminance(*b))),
+		StopOrder::Hue => colors.sort_by(|a, b| hue(*a).total_cmp(&hue(*b))),
+	}
+	let last = (colors.len() - 1).max(1) as f64;
+	let stops = colors
+		.iter()
+		.enumerate()
+		.map(|(i, c)| {
+			let [r, g, b] = c.map(|v| v.round().clamp(0.0, 255.0) as u8);
+			ColorStop { position: i as f64 / last, color: (r, g, b) }
+		})
+		.collect();
+	Ok(Gradient { stops, ..Gradient::default() })
+}
@@ -1,86 +1,587 @@
-//! Fractal rendering functions for Mandelbrot and Julia sets.
-//! Each function returns a flat RGB pixel buffer for the image.
+//! Fractal iteration for Mandelbrot and Julia sets, noise fields and
+//! circle-inversion fractals.
+//! Each fractal produces a field of per-pixel samples, which `coloring` turns into
+//! a flat RGB pixel buffer for the image.
 
-use crate::types::{ViewRect, Palette};
-use crate::palette::palette_color;
+use crate::types::{ViewRect, FractalType, JuliaMode, InverseParams, Formula, HybridStep, HybridParams, TrapShape, RenderSettings, ColoringParams, InteriorMode, DistanceMode};
+use crate::coloring::{Sample, MAX_ITER, colorize, count_histogram, histogram_from_counts};
+use std::ops::Range;
+use crate::complex::Complex;
+use crate::noise::noise_field;
+use crate::circles::{apollonian_field, kleinian_field};
 
-/// Render the Mandelbrot set for the given view and palette.
+/// |z|² the orbit is followed out to after escape, so the smooth count is free of bailout banding.
+const SMOOTH_BAILOUT: f64 = 1e6;
+/// Rows of samples computed at a time by `render_bands`.
+const BAND_ROWS: usize = 64;
+/// Most extra iterations run after escape for the smooth count.
+const SMOOTH_MAX_EXTRA: u32 = 8;
+/// Longest attracting cycle looked for at interior points.
+const MAX_PERIOD: u16 = 64;
+/// Squared distance at which an orbit is taken to have returned to its start.
+const PERIOD_EPSILON_SQR: f64 = 1e-10;
+
+/// Render whichever fractal `settings` selects.
 ///
 /// * `width`, `height` - Output image size in pixels
-/// * `view` - Complex plane region to render
-/// * `palette` - Color palette
-/// * `user_palette` - User-defined gradient colors
+/// * `settings` - View, palette, coloring and fractal parameters
+///
+/// Returns a flat RGBA buffer (row-major order) of float channels, 0.0 to 1.0.
+pub fn render_fractal(width: usize, height: usize, settings: &RenderSettings) -> Vec<f32> {
+	colorize(&compute_field(width, height, settings), width, settings)
+}
+
+/// Render whichever fractal `settings` selects a band of rows at a time, passing each
+/// band's RGBA floats (row-major, top band first) to `emit`.
 ///
-/// Returns a flat RGB buffer (row-major order).
-pub fn render_mandelbrot(
-	width: usize,
-	height: usize,
-	view: ViewRect,
-	palette: Palette,
-	user_palette: &[(u8, u8, u8); 2],
-) -> Vec<u8> {
-	let mut pixels = vec![0u8; width * height * 3];
+/// Only one band of samples is held at a time, so large exports need little memory.
+/// Fields drawn over the whole view at once (MIIM Julia boundary, Kleinian limit set)
+/// are kept as a compact raster instead. Histogram coloring takes a first pass over the
+/// bands that only counts values.
+pub fn render_bands(width: usize, height: usize, settings: &RenderSettings, mut emit: impl FnMut(&[f32])) {
+	let raster = whole_view_raster(width, height, settings);
+	let bands = || (0..height).step_by(BAND_ROWS).map(move |start| start..(start + BAND_ROWS).min(height));
+	let mut settings = settings.clone();
+	if settings.coloring.uses_histogram() && settings.coloring.frozen_histogram.is_none() {
+		let mut counts = Vec::new();
+		for rows in bands() {
+			count_histogram(&band_field(width, height, rows, &settings, raster.as_deref()), settings.coloring.smooth, &mut counts);
+		}
+		settings.coloring.frozen_histogram = Some(histogram_from_counts(&counts));
+	}
+	for rows in bands() {
+		// One extra row on each side gives the relief lighting its neighbours
+		let start = rows.start.saturating_sub(1);
+		let end = (rows.end + 1).min(height);
+		let pixels = colorize(&band_field(width, height, start..end, &settings, raster.as_deref()), width, &settings);
+		let skip = rows.start - start;
+		emit(&pixels[skip * width * 4..(skip + rows.len()) * width * 4]);
+	}
+}
+
+/// The whole-view raster of fields that cannot be computed a band at a time, if the
+/// selected fractal is one.
+fn whole_view_raster(width: usize, height: usize, settings: &RenderSettings) -> Option<Vec<u32>> {
+	match settings.fractal_type {
+		FractalType::Julia if settings.julia_mode != JuliaMode::EscapeTime => {
+			Some(inverse_julia_field(width, height, settings.view, settings.julia_param, &settings.inverse))
+		}
+		FractalType::Kleinian => Some(kleinian_field(width, height, settings.view, &settings.kleinian)),
+		_ => None,
+	}
+}
+
+/// Compute the samples of image rows `rows` out of `height`.
+///
+/// * `raster` - The fractal's whole-view raster from `whole_view_raster`, if it has one
+fn band_field(width: usize, height: usize, rows: Range<usize>, settings: &RenderSettings, raster: Option<&[u32]>) -> Vec<Sample> {
+	let view = settings.view;
+	let dy = (view.max_y - view.min_y) / height as f64;
+	let band_view = ViewRect { min_y: view.min_y + rows.start as f64 * dy, max_y: view.min_y + rows.end as f64 * dy, ..view };
+	let band = RenderSettings { view: band_view, ..settings.clone() };
+	let Some(raster) = raster else {
+		return compute_field(width, rows.len(), &band);
+	};
+	let raster = &raster[rows.start * width..rows.end * width];
+	if settings.fractal_type == FractalType::Julia && settings.julia_mode == JuliaMode::Overlay {
+		let mut field = julia_field(width, rows.len(), &band);
+		mark_overlay(&mut field, raster);
+		field
+	} else {
+		raster.iter().map(|&v| Sample::from_value(v)).collect()
+	}
+}
+
+/// Compute the per-pixel samples for whichever fractal `settings` selects, without coloring.
+///
+/// Returns one sample per pixel (row-major order).
+pub fn compute_field(width: usize, height: usize, settings: &RenderSettings) -> Vec<Sample> {
+	let view = settings.view;
+	match settings.fractal_type {
+		FractalType::Mandelbrot => mandelbrot_field(width, height, settings),
+		FractalType::Julia => match settings.julia_mode {
+			JuliaMode::EscapeTime => julia_field(width, height, settings),
+			JuliaMode::Inverse => inverse_julia_field(width, height, view, settings.julia_param, &settings.inverse)
+				.into_iter()
+				.map(Sample::from_value)
+				.collect(),
+			JuliaMode::Overlay => {
+				let mut field = julia_field(width, height, settings);
+				overlay_julia_boundary(&mut field, width, height, view, settings.julia_param, &settings.inverse);
+				field
+			}
+		},
+		// Heights use 0..=254 so no pixel is treated as interior
+		FractalType::Noise => noise_field(width, height, view, &settings.noise)
+			.iter()
+			.map(|h| Sample::from_smooth(h * 254.0))
+			.collect(),
+		FractalType::Apollonian => apollonian_field(width, height, view, &settings.apollonian)
+			.into_iter()
+			.map(Sample::from_value)
+			.collect(),
+		FractalType::Kleinian => kleinian_field(width, height, view, &settings.kleinian)
+			.into_iter()
+			.map(Sample::from_value)
+			.collect(),
+		FractalType::Hybrid => hybrid_field(width, height, settings),
+	}
+}
+
+/// Evaluate `sample` at the complex-plane point of every pixel.
+fn plane_field(width: usize, height: usize, view: ViewRect, sample: impl Fn(Complex) -> Sample) -> Vec<Sample> {
+	let mut field = Vec::with_capacity(width * height);
 	for y in 0..height {
 		for x in 0..width {
 			// Map pixel to complex plane
-			let cx = view.min_x + x as f64 / width as f64 * (view.max_x - view.min_x);
-			let cy = view.min_y + y as f64 / height as f64 * (view.max_y - view.min_y);
-			let mut zx = 0.0;
-			let mut zy = 0.0;
-			let mut i = 0;
-			// Iterate z = z^2 + c until escape or max iterations
-			while zx * zx + zy * zy < 4.0 && i < 255 {
-				let tmp = zx * zx - zy * zy + cx;
-				zy = 2.0 * zx * zy + cy;
-				zx = tmp;
-				i += 1;
+			let p = Complex::new(
+				view.min_x + x as f64 / width as f64 * (view.max_x - view.min_x),
+				view.min_y + y as f64 / height as f64 * (view.max_y - view.min_y),
+			);
+			field.push(sample(p));
+		}
+	}
+	field
+}
+
+/// Which orbit data the coloring reads, so the tracker skips everything else.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct OrbitNeeds {
+	/// Closest approach to the orbit trap
+	pub trap: bool,
+	/// Stripe, triangle inequality and curvature averages
+	pub averages: [bool; 3],
+	/// Divergent and convergent exponential smoothing sums
+	pub exponential: [bool; 2],
+	/// Period and multiplier of the attracting cycle at interior points
+	pub cycle: bool,
+	/// Exterior distance estimate and its normal
+	pub distance: bool,
+}
+
+impl OrbitNeeds {
+	/// The orbit data read by a coloring configuration.
+	pub fn new(coloring: &ColoringParams) -> Self {
+		Self {
+			trap: coloring.uses_trap(),
+			averages: coloring.uses_averages(),
+			exponential: coloring.uses_exponential(),
+			cycle: matches!(coloring.interior.mode, InteriorMode::Period | InteriorMode::Multiplier),
+			distance: coloring.distance.mode != DistanceMode::Off || coloring.lighting.enabled,
+		}
+	}
+
+	/// Is anything tracked along the orbit (as opposed to at its end)?
+	fn tracks_orbit(&self) -> bool {
+		self.trap || self.averages.contains(&true) || self.exponential.contains(&true)
+	}
+
+	/// Does a field tracked with these needs have everything `other` reads?
+	pub fn covers(&self, other: &OrbitNeeds) -> bool {
+		let covers = |have: &[bool], want: &[bool]| have.iter().zip(want).all(|(h, w)| *h || !*w);
+		covers(&[self.trap, self.cycle, self.distance], &[other.trap, other.cycle, other.distance])
+			&& covers(&self.averages, &other.averages)
+			&& covers(&self.exponential, &other.exponential)
+	}
+}
+
+/// Coloring data gathered along an orbit while it is iterated: the closest approach to
+/// the orbit trap, the running stripe, triangle inequality and curvature averages, and
+/// the exponential smoothing sums.
+struct OrbitTracker {
+	/// Which of the data below is tracked
+	needs: OrbitNeeds,
+	trap_center: Complex,
+	/// Rotates offsets from the trap center into the trap's frame
+	trap_rotation: Complex,
+	trap_shape: TrapShape,
+	trap_size: f64,
+	trap_width: f64,
+	trap_distance: f64,
+	trap_iteration: u32,
+	/// Offset from the trap center at the closest approach, in the trap's frame
+	trap_point: Complex,
+	stripe_density: f64,
+	/// The parameter c, needed for the triangle inequality bounds
+	c: Complex,
+	/// Running sums of the stripe, triangle inequality and curvature terms
+	sums: [f64; 3],
+	/// Number of terms in each sum
+	counts: [u32; 3],
+	/// The latest term added to each sum
+	last: [f64; 3],
+	/// The two orbit points before the latest one
+	prev: [Complex; 2],
+	/// Sums of exp(-|z|) (divergent) and exp(-1/|z - z_prev|) (convergent)
+	exponential: [f64; 2],
+}
+
+impl OrbitTracker {
+	fn new(settings: &RenderSettings, needs: OrbitNeeds, z0: Complex, c: Complex) -> Self {
+		let trap = &settings.trap;
+		let angle = -trap.angle.to_radians();
+		Self {
+			needs,
+			trap_center: trap.center.into(),
+			trap_rotation: Complex::new(angle.cos(), angle.sin()),
+			trap_shape: trap.shape,
+			trap_size: trap.size,
+			trap_width: trap.width,
+			trap_distance: f64::INFINITY,
+			trap_iteration: 0,
+			trap_point: Complex::default(),
+			stripe_density: settings.averages.stripe_density,
+			c,
+			sums: [0.0; 3],
+			counts: [0; 3],
+			last: [0.0; 3],
+			prev: [z0, z0],
+			exponential: [0.0; 2],
+		}
+	}
+
+	/// Record orbit point `z`, reached at iteration `i`, against the orbit trap.
+	fn visit_trap(&mut self, z: Complex, i: u32) {
+		if !self.needs.trap {
+			return;
+		}
+		let d = self.trap_rotation * (z - self.trap_center);
+		let distance = match self.trap_shape {
+			TrapShape::Point => d.abs(),
+			TrapShape::Line => d.im.abs(),
+			TrapShape::Cross => d.re.abs().min(d.im.abs()),
+			TrapShape::Circle => (d.abs() - self.trap_size).abs(),
+			TrapShape::Ring => ((d.abs() - self.trap_size).abs() - self.trap_width / 2.0).max(0.0),
+			TrapShape::Grid => {
+				let spacing = self.trap_size.max(1e-12);
+				let off = |v: f64| (v - (v / spacing).round() * spacing).abs();
+				off(d.re).min(off(d.im))
 			}
-			let idx = (y * width + x) * 3;
-			let color = palette_color(i, palette, user_palette);
-			pixels[idx..idx + 3].copy_from_slice(&color);
+		};
+		if distance < self.trap_distance {
+			self.trap_distance = distance;
+			self.trap_iteration = i;
+			self.trap_point = d;
 		}
 	}
-	pixels
+
+	/// Add orbit point `z` to the averages and exponential smoothing sums.
+	fn visit_averages(&mut self, z: Complex) {
+		let OrbitNeeds { averages, exponential, .. } = self.needs;
+		if exponential[0] {
+			self.exponential[0] += (-z.abs()).exp();
+		}
+		if exponential[1] {
+			let step = (z - self.prev[0]).abs();
+			if step > 0.0 {
+				self.exponential[1] += (-1.0 / step).exp();
+			}
+		}
+		let mut add = |k: usize, term: f64| {
+			self.sums[k] += term;
+			self.counts[k] += 1;
+			self.last[k] = term;
+		};
+		if averages[0] {
+			add(0, 0.5 + 0.5 * (self.stripe_density * z.im.atan2(z.re)).sin());
+		}
+		if averages[1] {
+			// |z| lies between ||f(z_prev)| - |c|| and |f(z_prev)| + |c|, where f(z_prev) = z - c
+			let (fz, c) = ((z - self.c).abs(), self.c.abs());
+			let (low, high) = ((fz - c).abs(), fz + c);
+			if high - low > 1e-12 {
+				add(1, (z.abs() - low) / (high - low));
+			}
+		}
+		if averages[2] {
+			let (turn, before) = (z - self.prev[0], self.prev[0] - self.prev[1]);
+			if turn.norm_sqr() > 0.0 && before.norm_sqr() > 0.0 {
+				let q = turn / before;
+				add(2, q.im.atan2(q.re).abs() / std::f64::consts::PI);
+			}
+		}
+		self.prev = [z, self.prev[0]];
+	}
+
+	/// Store the trap distance, closest approach and averages in a sample.
+	///
+	/// `mix` (0 to 1) blends each average towards its value without the latest term,
+	/// which removes the banding between iteration counts.
+	fn apply(&self, mut sample: Sample, mix: f64) -> Sample {
+		sample.trap_distance = self.trap_distance as f32;
+		sample.trap_iteration = self.trap_iteration as u16;
+		sample.trap_point = [self.trap_point.re as f32, self.trap_point.im as f32];
+		let averages: [f64; 3] = std::array::from_fn(|k| {
+			let n = self.counts[k] as f64;
+			if n == 0.0 {
+				return 0.0;
+			}
+			let current = self.sums[k] / n;
+			let previous = if n > 1.0 { (self.sums[k] - self.last[k]) / (n - 1.0) } else { current };
+			current + (previous - current) * mix
+		});
+		sample.averages = averages.map(|v| v as f32);
+		sample.exponential = self.exponential.map(|v| v as f32);
+		sample
+	}
 }
 
-/// Render the Julia set for the given view, palette, and parameter c.
+/// Fraction of the last iteration an orbit ending at |z|² = `norm_sqr` overshot the
+/// smooth bailout by, for a formula of degree `power`. Used to interpolate averages.
+fn overshoot(norm_sqr: f64, power: f64) -> f64 {
+	let ratio = norm_sqr.ln() / SMOOTH_BAILOUT.ln();
+	let mix = ratio.ln() / power.max(1.01).ln();
+	if mix.is_finite() { mix.clamp(0.0, 1.0) } else { 0.0 }
+}
+
+/// Width of one pixel in the complex plane.
+fn pixel_size(width: usize, view: ViewRect) -> f64 {
+	(view.max_x - view.min_x) / width as f64
+}
+
+/// Iterate z = z^2 + c from `z0` until escape or the iteration limit.
+///
+/// The derivative of z is tracked for the distance estimate: with respect to c for
+/// the Mandelbrot set, or to `z0` when `julia` is set.
+///
+/// * `pixel` - Pixel size, used to express the distance estimate in pixels
+/// * `needs` - Orbit data to track for the coloring
+fn escape_time(z0: Complex, c: Complex, julia: bool, pixel: f64, needs: OrbitNeeds, settings: &RenderSettings) -> Sample {
+	let mut orbit = OrbitTracker::new(settings, needs, z0, c);
+	let track = needs.tracks_orbit();
+	let mut z = z0;
+	let (mut dz, dc) = if julia { (Complex::new(1.0, 0.0), 0.0) } else { (Complex::default(), 1.0) };
+	let mut i = 0;
+	while z.norm_sqr() < 4.0 && i < MAX_ITER {
+		if needs.distance {
+			dz = z * dz * 2.0 + Complex::new(dc, 0.0);
+		}
+		z = z * z + c;
+		i += 1;
+		if track {
+			orbit.visit_trap(z, i);
+			orbit.visit_averages(z);
+		}
+	}
+	if i >= MAX_ITER {
+		let (period, multiplier) = if needs.cycle { attracting_cycle(z, c) } else { (0, 0.0) };
+		return orbit.apply(Sample::interior(z.into(), period, multiplier), 0.0);
+	}
+	// Follow the orbit a little further for the smooth count and averages
+	let mut extra = 0;
+	while z.norm_sqr() < SMOOTH_BAILOUT && extra < SMOOTH_MAX_EXTRA {
+		if needs.distance {
+			dz = z * dz * 2.0 + Complex::new(dc, 0.0);
+		}
+		z = z * z + c;
+		extra += 1;
+		if track {
+			orbit.visit_averages(z);
+		}
+	}
+	let mut sample = orbit.apply(Sample::escaped(i, extra, z.norm_sqr(), 2.0), overshoot(z.norm_sqr(), 2.0));
+	sample.angle = z.arg() as f32;
+	if !needs.distance {
+		return sample;
+	}
+	// Exterior distance estimate |z| ln|z| / |dz|
+	let distance = z.abs() * z.abs().ln() / dz.abs() / pixel;
+	if distance.is_finite() {
+		sample.distance = distance as f32;
+	}
+	// The potential increases along z / dz, away from the set
+	let u = z / dz;
+	let u_len = u.abs();
+	if u_len.is_finite() && u_len > 0.0 {
+		sample.normal = [(u.re / u_len) as f32, (u.im / u_len) as f32];
+	}
+	sample
+}
+
+/// Find the attracting cycle of z^2 + c that an interior orbit has settled on.
+///
+/// Iterates on from `z` until the orbit comes back to it, accumulating the derivative
+/// of the cycle map (the product of 2z over the cycle).
+///
+/// Returns (period, multiplier magnitude), with period 0 if no cycle up to
+/// `MAX_PERIOD` was found.
+fn attracting_cycle(z: Complex, c: Complex) -> (u16, f64) {
+	let mut w = z;
+	let mut derivative = Complex::new(1.0, 0.0);
+	for period in 1..=MAX_PERIOD {
+		derivative = derivative * w * 2.0;
+		w = w * w + c;
+		if (w - z).norm_sqr() < PERIOD_EPSILON_SQR {
+			return (period, derivative.abs());
+		}
+	}
+	(0, 0.0)
+}
+
+/// Compute the Mandelbrot set samples for the given view.
 ///
 /// * `width`, `height` - Output image size in pixels
-/// * `view` - Complex plane region to render
-/// * `palette` - Color palette
-/// * `user_palette` - User-defined gradient colors
-/// * `c` - Julia set parameter (re, im)
+/// * `settings` - View, orbit trap and average settings
+pub fn mandelbrot_field(width: usize, height: usize, settings: &RenderSettings) -> Vec<Sample> {
+	let pixel = pixel_size(width, settings.view);
+	let needs = OrbitNeeds::new(&settings.coloring);
+	plane_field(width, height, settings.view, |c| escape_time(Complex::default(), c, false, pixel, needs, settings))
+}
+
+/// Compute the Julia set samples for the view and parameter c in `settings`.
 ///
-/// Returns a flat RGB buffer (row-major order).
-pub fn render_julia(
-	width: usize,
-	height: usize,
-	view: ViewRect,
-	palette: Palette,
-	user_palette: &[(u8, u8, u8); 2],
-	c: (f64, f64),
-) -> Vec<u8> {
-	let mut pixels = vec![0u8; width * height * 3];
-	for y in 0..height {
-		for x in 0..width {
-			// Map pixel to complex plane
-			let zx0 = view.min_x + x as f64 / width as f64 * (view.max_x - view.min_x);
-			let zy0 = view.min_y + y as f64 / height as f64 * (view.max_y - view.min_y);
-			let (cx, cy) = c;
-			let mut zx = zx0;
-			let mut zy = zy0;
-			let mut i = 0;
-			// Iterate z = z^2 + c until escape or max iterations
-			while zx * zx + zy * zy < 4.0 && i < 255 {
-				let tmp = zx * zx - zy * zy + cx;
-				zy = 2.0 * zx * zy + cy;
-				zx = tmp;
-				i += 1;
+/// * `width`, `height` - Output image size in pixels
+/// * `settings` - View, Julia parameter, orbit trap and average settings
+pub fn julia_field(width: usize, height: usize, settings: &RenderSettings) -> Vec<Sample> {
+	let c = Complex::from(settings.julia_param);
+	let pixel = pixel_size(width, settings.view);
+	let needs = OrbitNeeds::new(&settings.coloring);
+	plane_field(width, height, settings.view, |z0| escape_time(z0, c, true, pixel, needs, settings))
+}
+
+/// Side length of the coarse grid used to prune preimages that fall outside the view.
+const IIM_PRUNE_GRID: usize = 1024;
+/// Upper bound on preimages visited by one inverse iteration render.
+const IIM_MAX_POINTS: usize = 50_000_000;
+
+/// Plot the Julia set boundary for z^2 + c by the modified inverse iteration method.
+///
+/// Starting from the repelling fixed point, both preimages ±sqrt(z - c) are explored
+/// depth-first. A branch is pruned once the pixel it lands on has been hit
+/// `params.max_hits` times, so effort is spread evenly along the boundary.
+///
+/// Returns one value per pixel in 0..=255: the scaled depth of the first hit, or 255
+/// for pixels the boundary does not touch.
+pub fn inverse_julia_field(width: usize, height: usize, view: ViewRect, c: (f64, f64), params: &InverseParams) -> Vec<u32> {
+	let c = Complex::from(c);
+	let mut field = vec![255u32; width * height];
+	let mut hits = vec![0u32; width * height];
+	// Points outside the view are pruned on a coarse grid over the whole Julia set
+	let radius = c.abs().max(2.0);
+	let mut outside_hits = vec![0u32; IIM_PRUNE_GRID * IIM_PRUNE_GRID];
+	let max_depth = params.max_depth.max(1);
+
+	// Repelling fixed point of z^2 + c: the root of z^2 - z + c with |2z| > 1
+	let root = (Complex::new(0.25, 0.0) - c).sqrt();
+	let start = [Complex::new(0.5, 0.0) + root, Complex::new(0.5, 0.0) - root]
+		.into_iter()
+		.max_by(|a, b| a.norm_sqr().total_cmp(&b.norm_sqr()))
+		.unwrap_or_default();
+
+	let mut stack = vec![(start, 0u32)];
+	let mut visited = 0;
+	while let Some((z, depth)) = stack.pop() {
+		visited += 1;
+		if visited > IIM_MAX_POINTS {
+			break;
+		}
+		let px = (z.re - view.min_x) / (view.max_x - view.min_x) * width as f64;
+		let py = (z.im - view.min_y) / (view.max_y - view.min_y) * height as f64;
+		let count = if px >= 0.0 && py >= 0.0 && (px as usize) < width && (py as usize) < height {
+			let idx = py as usize * width + px as usize;
+			field[idx] = field[idx].min((depth * 254 / max_depth).min(254));
+			hits[idx] += 1;
+			hits[idx]
+		} else {
+			let gx = ((z.re + radius) / (2.0 * radius) * IIM_PRUNE_GRID as f64).clamp(0.0, (IIM_PRUNE_GRID - 1) as f64) as usize;
+			let gy = ((z.im + radius) / (2.0 * radius) * IIM_PRUNE_GRID as f64).clamp(0.0, (IIM_PRUNE_GRID - 1) as f64) as usize;
+			let idx = gy * IIM_PRUNE_GRID + gx;
+			outside_hits[idx] += 1;
+			outside_hits[idx]
+		};
+		if count > params.max_hits.max(1) || depth >= max_depth {
+			continue;
+		}
+		let w = (z - c).sqrt();
+		stack.push((w, depth + 1));
+		stack.push((-w, depth + 1));
+	}
+	field
+}
+
+/// Mark the inverse iteration boundary to be drawn over an escape-time field.
+pub fn overlay_julia_boundary(field: &mut [Sample], width: usize, height: usize, view: ViewRect, c: (f64, f64), params: &InverseParams) {
+	mark_overlay(field, &inverse_julia_field(width, height, view, c, params));
+}
+
+/// Mark the samples where an inverse iteration raster hit the boundary as overlay.
+fn mark_overlay(field: &mut [Sample], boundary: &[u32]) {
+	for (sample, &v) in field.iter_mut().zip(boundary) {
+		if v < 255 {
+			sample.overlay = true;
+		}
+	}
+}
+
+/// Apply one hybrid step's formula to z.
+fn hybrid_step(step: &HybridStep, z: Complex, c: Complex) -> Complex {
+	match step.formula {
+		Formula::Mandelbrot => z.powf(step.power) + c,
+		Formula::BurningShip => Complex::new(z.re.abs(), z.im.abs()).powf(step.power) + c,
+		Formula::Tricorn => z.conj().powf(step.power) + c,
+		Formula::Celtic => {
+			let w = z.powf(step.power);
+			Complex::new(w.re.abs(), w.im) + c
+		}
+	}
+}
+
+/// Iterate a hybrid formula list from z0 with parameter c.
+fn hybrid_sample(params: &HybridParams, mut z: Complex, c: Complex, needs: OrbitNeeds, settings: &RenderSettings) -> Sample {
+	const DEFAULT_STEP: HybridStep = HybridStep { formula: Formula::Mandelbrot, power: 2.0, iterations: 1 };
+	let steps: &[HybridStep] = if params.steps.is_empty() { &[DEFAULT_STEP] } else { &params.steps };
+	let mut current = 0;
+	let mut left = steps[0].iterations.max(1);
+	let mut orbit = OrbitTracker::new(settings, needs, z, c);
+	let track = needs.tracks_orbit();
+	let mut i = 0;
+	while z.norm_sqr() < 4.0 && i < MAX_ITER {
+		z = hybrid_step(&steps[current], z, c);
+		i += 1;
+		if track {
+			orbit.visit_trap(z, i);
+			orbit.visit_averages(z);
+		}
+		left -= 1;
+		if left == 0 {
+			if current + 1 < steps.len() {
+				current += 1;
+			} else if params.repeat {
+				current = 0;
 			}
-			let idx = (y * width + x) * 3;
-			let color = palette_color(i, palette, user_palette);
-			pixels[idx..idx + 3].copy_from_slice(&color);
+			// Without repeat the last step keeps running until escape
+			left = steps[current].iterations.max(1);
 		}
 	}
-	pixels
+	if i >= MAX_ITER {
+		// Cycles of mixed formulas are not tracked
+		return orbit.apply(Sample::interior(z.into(), 0, 0.0), 0.0);
+	}
+	// The smooth count follows the step that was running at escape
+	let mut extra = 0;
+	while z.norm_sqr() < SMOOTH_BAILOUT && extra < SMOOTH_MAX_EXTRA {
+		z = hybrid_step(&steps[current], z, c);
+		extra += 1;
+		if track {
+			orbit.visit_averages(z);
+		}
+	}
+	let power = steps[current].power;
+	let mut sample = orbit.apply(Sample::escaped(i, extra, z.norm_sqr(), power), overshoot(z.norm_sqr(), power));
+	sample.angle = z.arg() as f32;
+	sample
+}
+
+/// Compute a hybrid fractal that cycles through a list of formula steps.
+///
+/// * `width`, `height` - Output image size in pixels
+/// * `settings` - View, hybrid formula list (c is fixed to `julia_param` when
+///   `hybrid.julia` is set), orbit trap and average settings
+pub fn hybrid_field(width: usize, height: usize, settings: &RenderSettings) -> Vec<Sample> {
+	let params = &settings.hybrid;
+	let needs = OrbitNeeds::new(&settings.coloring);
+	plane_field(width, height, settings.view, |p| {
+		let (z0, c) = if params.julia { (p, settings.julia_param.into()) } else { (Complex::default(), p) };
+		hybrid_sample(params, z0, c, needs, settings)
+	})
 }
@@ -0,0 +1,51 @@
+//! Seeded palette generator: random but harmonious gradients from color-harmony rules.
+//! The same rule, seed and stop count always give the same gradient.
+
+use crate::types::{ColorSpace, ColorStop, GeneratorParams, Gradient, HarmonyRule};
+use crate::colorspace::{from_oklch, to_bytes};
+use crate::noise::{hash, unit};
+
+/// OKLCh lightness of the darkest and lightest stops.
+const LIGHTNESS_RANGE: (f64, f64) = (0.25, 0.92);
+/// OKLCh chroma range of the stops; kept moderate so colors stay inside the sRGB gamut.
+const CHROMA_RANGE: (f64, f64) = (0.08, 0.19);
+/// Largest random shift of a stop's hue away from its harmony hue, in degrees.
+const HUE_JITTER: f64 = 12.0;
+
+/// Hue offsets from the base hue, in degrees, for a harmony rule.
+fn harmony_offsets(rule: HarmonyRule) -> &'static [f64] {
+	match rule {
+		HarmonyRule::Complementary => &[0.0, 180.0],
+		HarmonyRule::Analogous => &[-30.0, 0.0, 30.0],
+		HarmonyRule::Triadic => &[0.0, 120.0, 240.0],
+		HarmonyRule::SplitComplementary => &[0.0, 150.0, 210.0],
+		HarmonyRule::Monochrome => &[0.0],
+	}
+}
+
+/// Generate a gradient from a harmony rule and seed.
+///
+/// Stops run from dark to light so the palette reads as a smooth ramp; each takes one of
+/// the rule's hues in turn, with a little random hue, lightness and chroma variation.
+pub fn generate_gradient(params: &GeneratorParams) -> Gradient {
+	// Each random choice hashes the stop index and a choice number with the seed
+	let random = |stop: usize, choice: i64| unit(hash(stop as i64, choice, params.seed));
+	let offsets = harmony_offsets(params.rule);
+	let base = random(0, 0) * 360.0;
+	// Rotate which harmony hue comes first, so seeds differ in more than the base hue
+	let first = (random(0, 1) * offsets.len() as f64) as usize;
+	let count = params.stops.max(2);
+	let (dark, light) = LIGHTNESS_RANGE;
+	let stops = (0..count)
+		.map(|i| {
+			let position = i as f64 / (count - 1) as f64;
+			let hue = base + offsets[(first + i) % offsets.len()] + (random(i, 2) * 2.0 - 1.0) * HUE_JITTER;
+			let step = (light - dark) / (count - 1) as f64;
+			let lightness = dark + position * (light - dark) + (random(i, 3) - 0.5) * step * 0.5;
+			let chroma = CHROMA_RANGE.0 + random(i, 4) * (CHROMA_RANGE.1 - CHROMA_RANGE.0);
+			let [r, g, b] = to_bytes(from_oklch([lightness, chroma, hue.to_radians()]));
+			ColorStop { position, color: (r, g, b) }
+		})
+		.collect();
+	Gradient { stops, interpolation: ColorSpace::Oklab, ..Gradient::default() }
+}
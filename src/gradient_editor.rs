@@ -0,0 +1,126 @@
+//! Gradient editor widget for the user-defined palette.
+//! Shows the gradient as a bar with draggable stop handles underneath.
+
+use crate::types::{Gradient, ColorStop, RepeatMode, COLOR_SPACE_NAMES};
+use crate::palette::interpolate_stops;
+use crate::colorspace::to_bytes;
+use eframe::egui;
+
+/// Size of the gradient preview bar in points.
+const BAR_SIZE: egui::Vec2 = egui::vec2(320.0, 24.0);
+/// Size of a stop handle in points.
+const HANDLE_SIZE: egui::Vec2 = egui::vec2(10.0, 14.0);
+
+/// Convert an (r, g, b) tuple to an egui color.
+fn to_color32(c: (u8, u8, u8)) -> egui::Color32 {
+	egui::Color32::from_rgb(c.0, c.1, c.2)
+}
+
+/// Show the gradient editor: click the bar to add a stop, drag a handle to move it,
+/// click a handle to select it, then recolor or delete it with the controls on the right.
+///
+/// * `selected` - Index of the selected stop, kept by the caller between frames
+///
+/// Returns true if the gradient changed.
+pub fn gradient_editor(ui: &mut egui::Ui, gradient: &mut Gradient, selected: &mut Option<usize>) -> bool {
+	let mut changed = false;
+	if selected.is_some_and(|i| i >= gradient.stops.len()) {
+		*selected = None;
+	}
+
+	ui.horizontal(|ui| {
+		let (area, bar_response) = ui.allocate_exact_size(
+			egui::vec2(BAR_SIZE.x, BAR_SIZE.y + HANDLE_SIZE.y),
+			egui::Sense::click(),
+		);
+		let bar = egui::Rect::from_min_size(area.min, BAR_SIZE);
+
+		// Draw the bar as thin vertical slices of the interpolated gradient
+		let painter = ui.painter();
+		let slices = BAR_SIZE.x as usize / 2;
+		for s in 0..slices {
+			let t = s as f64 / (slices - 1) as f64;
+			let [r, g, b] = to_bytes(interpolate_stops(&gradient.stops, t, gradient.interpolation));
+			let x0 = bar.min.x + s as f32 * 2.0;
+			let slice = egui::Rect::from_min_max(egui::pos2(x0, bar.min.y), egui::pos2(x0 + 2.0, bar.max.y));
+			painter.rect_filled(slice, 0.0, egui::Color32::from_rgb(r, g, b));
+		}
+		painter.rect_stroke(bar, 0.0, (1.0, egui::Color32::GRAY));
+
+		// Clicking the bar adds a stop with the color already shown there
+		if bar_response.clicked()
+			&& let Some(pos) = bar_response.interact_pointer_pos()
+			&& bar.contains(pos)
+		{
+			let position = ((pos.x - bar.min.x) / bar.width()).clamp(0.0, 1.0) as f64;
+			let [r, g, b] = to_bytes(interpolate_stops(&gradient.stops, position, gradient.interpolation));
+			gradient.stops.push(ColorStop { position, color: (r, g, b) });
+			*selected = Some(gradient.stops.len() - 1);
+			changed = true;
+		}
+
+		// Stop handles below the bar
+		for (idx, stop) in gradient.stops.iter_mut().enumerate() {
+			let x = bar.min.x + stop.position as f32 * bar.width();
+			let handle = egui::Rect::from_center_size(
+				egui::pos2(x, bar.max.y + HANDLE_SIZE.y / 2.0),
+				HANDLE_SIZE,
+			);
+			let response = ui.interact(handle, ui.id().with(("gradient_stop", idx)), egui::Sense::click_and_drag());
+			if response.clicked() || response.drag_started() {
+				*selected = Some(idx);
+			}
+			if response.dragged() {
+				// A held handle reports a drag every frame, so only count actual movement
+				let position = (stop.position + (response.drag_delta().x / bar.width()) as f64).clamp(0.0, 1.0);
+				if position != stop.position {
+					stop.position = position;
+					changed = true;
+				}
+			}
+			let outline = if *selected == Some(idx) { egui::Color32::WHITE } else { egui::Color32::GRAY };
+			ui.painter().rect_filled(handle, 2.0, to_color32(stop.color));
+			ui.painter().rect_stroke(handle, 2.0, (if *selected == Some(idx) { 2.0 } else { 1.0 }, outline));
+		}
+
+		// Controls for the selected stop
+		if let Some(idx) = *selected {
+			let stop = &mut gradient.stops[idx];
+			let mut rgb = [stop.color.0, stop.color.1, stop.color.2];
+			if ui.color_edit_button_srgb(&mut rgb).changed() {
+				stop.color = (rgb[0], rgb[1], rgb[2]);
+				changed = true;
+			}
+			changed |= ui.add(egui::DragValue::new(&mut stop.position).speed(0.005).clamp_range(0.0..=1.0)).changed();
+			if ui.add_enabled(gradient.stops.len() > 2, egui::Button::new("Delete Stop")).clicked() {
+				gradient.stops.remove(idx);
+				*selected = None;
+				changed = true;
+			}
+		} else {
+			ui.label("Click the bar to add a stop, click a handle to edit it");
+		}
+	});
+
+	ui.horizontal(|ui| {
+		ui.label("Density:");
+		changed |= ui.add(egui::DragValue::new(&mut gradient.density).speed(0.05).clamp_range(0.01..=100.0)).changed();
+		ui.label("Offset:");
+		changed |= ui.add(egui::DragValue::new(&mut gradient.offset).speed(0.01)).changed();
+		ui.label("Outside range:");
+		changed |= ui.selectable_value(&mut gradient.repeat, RepeatMode::Repeat, "Repeat").changed();
+		changed |= ui.selectable_value(&mut gradient.repeat, RepeatMode::Mirror, "Mirror").changed();
+		changed |= ui.selectable_value(&mut gradient.repeat, RepeatMode::Clamp, "Clamp").changed();
+		ui.label("Blend in:");
+		egui::ComboBox::from_id_source("gradient_interpolation")
+			.selected_text(
+				COLOR_SPACE_NAMES.iter().find(|(_, c)| *c == gradient.interpolation).map(|(n, _)| *n).unwrap_or("Unknown")
+			)
+			.show_ui(ui, |cb| {
+				for (name, space) in COLOR_SPACE_NAMES {
+					changed |= cb.selectable_value(&mut gradient.interpolation, *space, *name).changed();
+				}
+			});
+	});
+	changed
+}
@@ -1,8 +1,17 @@
 mod app;
 mod types;
 mod palette;
+mod colorspace;
 mod fractal;
+mod coloring;
 mod save;
+mod noise;
+mod complex;
+mod circles;
+mod gradient_editor;
+mod extract;
+mod generator;
+mod texture;
 
 // Driver Program entry point
 
@@ -0,0 +1,180 @@
+//! Procedural noise generators for textures and terrain.
+//! Provides fractional Brownian motion, ridged multifractal and diamond-square plasma
+//! height fields, all normalized to [0, 1] and sampled through a `ViewRect`.
+
+use crate::types::{ViewRect, NoiseKind, NoiseParams};
+
+/// Side length (in complex-plane units) of one tile of the diamond-square plasma.
+const PLASMA_TILE: f64 = 4.0;
+/// Largest plasma grid is 2^PLASMA_MAX_LEVELS cells on a side.
+const PLASMA_MAX_LEVELS: u32 = 10;
+
+/// Hash a lattice point and seed to a pseudo-random u64 (SplitMix64 finalizer).
+pub fn hash(ix: i64, iy: i64, seed: u64) -> u64 {
+	let mut h = seed
+		^ (ix as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+		^ (iy as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+	h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	h ^ (h >> 31)
+}
+
+/// Map a hash to a float in [0, 1).
+pub fn unit(h: u64) -> f64 {
+	(h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Perlin-style gradient noise at (x, y), scaled to roughly [-1, 1].
+fn gradient_noise(x: f64, y: f64, seed: u64) -> f64 {
+	let x0 = x.floor();
+	let y0 = y.floor();
+	let (ix, iy) = (x0 as i64, y0 as i64);
+	let (fx, fy) = (x - x0, y - y0);
+	// Dot product of a random unit gradient at a lattice corner with the offset vector
+	let grad = |gx: i64, gy: i64, dx: f64, dy: f64| {
+		let angle = unit(hash(gx, gy, seed)) * std::f64::consts::TAU;
+		angle.cos() * dx + angle.sin() * dy
+	};
+	let n00 = grad(ix, iy, fx, fy);
+	let n10 = grad(ix + 1, iy, fx - 1.0, fy);
+	let n01 = grad(ix, iy + 1, fx, fy - 1.0);
+	let n11 = grad(ix + 1, iy + 1, fx - 1.0, fy - 1.0);
+	// Quintic fade curve for smooth interpolation
+	let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+	let (u, v) = (fade(fx), fade(fy));
+	let nx0 = n00 + u * (n10 - n00);
+	let nx1 = n01 + u * (n11 - n01);
+	(nx0 + v * (nx1 - nx0)) * std::f64::consts::SQRT_2
+}
+
+/// Fractional Brownian motion: a weighted sum of noise octaves, mapped to [0, 1].
+fn fbm(x: f64, y: f64, params: &NoiseParams) -> f64 {
+	let mut sum = 0.0;
+	let mut norm = 0.0;
+	let mut amp = 1.0;
+	let mut freq = 1.0;
+	for octave in 0..params.octaves {
+		sum += amp * gradient_noise(x * freq, y * freq, params.seed.wrapping_add(octave as u64));
+		norm += amp;
+		amp *= params.gain;
+		freq *= params.lacunarity;
+	}
+	if norm > 0.0 {
+		(sum / norm * 0.5 + 0.5).clamp(0.0, 1.0)
+	} else {
+		0.5
+	}
+}
+
+/// Ridged multifractal (after Musgrave): inverted absolute noise, where each octave
+/// is weighted by the previous one so ridges stay sharp and valleys stay smooth.
+fn ridged(x: f64, y: f64, params: &NoiseParams) -> f64 {
+	let mut sum = 0.0;
+	let mut norm = 0.0;
+	let mut amp = 1.0;
+	let mut freq = 1.0;
+	let mut weight = 1.0;
+	for octave in 0..params.octaves {
+		let n = gradient_noise(x * freq, y * freq, params.seed.wrapping_add(octave as u64));
+		let ridge = 1.0 - n.abs();
+		let signal = ridge * ridge * weight;
+		weight = (signal * 2.0).clamp(0.0, 1.0);
+		sum += signal * amp;
+		norm += amp;
+		amp *= params.gain;
+		freq *= params.lacunarity;
+	}
+	if norm > 0.0 {
+		(sum / norm).clamp(0.0, 1.0)
+	} else {
+		0.0
+	}
+}
+
+/// Build a wrapping diamond-square grid, normalized to [0, 1].
+///
+/// Returns the grid side length and the row-major values.
+fn plasma_grid(params: &NoiseParams) -> (usize, Vec<f64>) {
+	let levels = params.octaves.clamp(1, PLASMA_MAX_LEVELS);
+	let size = 1usize << levels;
+	let idx = |x: usize, y: usize| (y % size) * size + (x % size);
+	let rnd = |x: usize, y: usize| unit(hash(x as i64, y as i64, params.seed)) * 2.0 - 1.0;
+	let mut grid = vec![0.0; size * size];
+	grid[0] = rnd(0, 0);
+
+	let mut step = size;
+	let mut amp = 1.0;
+	while step > 1 {
+		let half = step / 2;
+		// Diamond step: the center of each square gets the average of its corners
+		for y in (0..size).step_by(step) {
+			for x in (0..size).step_by(step) {
+				let avg = (grid[idx(x, y)]
+					+ grid[idx(x + step, y)]
+					+ grid[idx(x, y + step)]
+					+ grid[idx(x + step, y + step)])
+					/ 4.0;
+				grid[idx(x + half, y + half)] = avg + amp * rnd(x + half, y + half);
+			}
+		}
+		// Square step: each edge midpoint gets the average of its four neighbours
+		for y in (0..size).step_by(half) {
+			let x_start = if (y / half).is_multiple_of(2) { half } else { 0 };
+			for x in (x_start..size).step_by(step) {
+				let avg = (grid[idx(x + size - half, y)]
+					+ grid[idx(x + half, y)]
+					+ grid[idx(x, y + size - half)]
+					+ grid[idx(x, y + half)])
+					/ 4.0;
+				grid[idx(x, y)] = avg + amp * rnd(x, y);
+			}
+		}
+		step = half;
+		amp *= params.gain;
+	}
+
+	let min = grid.iter().cloned().fold(f64::INFINITY, f64::min);
+	let max = grid.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+	let range = (max - min).max(f64::EPSILON);
+	for v in grid.iter_mut() {
+		*v = (*v - min) / range;
+	}
+	(size, grid)
+}
+
+/// Bilinearly sample the wrapping plasma grid at complex-plane position (x, y).
+fn sample_plasma(size: usize, grid: &[f64], x: f64, y: f64) -> f64 {
+	let gx = (x / PLASMA_TILE).rem_euclid(1.0) * size as f64;
+	let gy = (y / PLASMA_TILE).rem_euclid(1.0) * size as f64;
+	let (x0, y0) = (gx.floor() as usize % size, gy.floor() as usize % size);
+	let (x1, y1) = ((x0 + 1) % size, (y0 + 1) % size);
+	let (fx, fy) = (gx.fract(), gy.fract());
+	let top = grid[y0 * size + x0] * (1.0 - fx) + grid[y0 * size + x1] * fx;
+	let bottom = grid[y1 * size + x0] * (1.0 - fx) + grid[y1 * size + x1] * fx;
+	top * (1.0 - fy) + bottom * fy
+}
+
+/// Generate a noise height field for the given view.
+///
+/// * `width`, `height` - Output size in pixels
+/// * `view` - Region of the plane to sample
+/// * `params` - Generator settings
+///
+/// Returns one height per pixel in [0, 1] (row-major order).
+pub fn noise_field(width: usize, height: usize, view: ViewRect, params: &NoiseParams) -> Vec<f64> {
+	// The plasma grid is built once per field and then sampled like the other generators
+	let plasma = (params.kind == NoiseKind::Plasma).then(|| plasma_grid(params));
+	let mut field = vec![0.0; width * height];
+	for y in 0..height {
+		for x in 0..width {
+			let px = view.min_x + x as f64 / width as f64 * (view.max_x - view.min_x);
+			let py = view.min_y + y as f64 / height as f64 * (view.max_y - view.min_y);
+			field[y * width + x] = match (&plasma, params.kind) {
+				(Some((size, grid)), _) => sample_plasma(*size, grid, px, py),
+				(None, NoiseKind::Ridged) => ridged(px, py, params),
+				(None, _) => fbm(px, py, params),
+			};
+		}
+	}
+	field
+}
@@ -1,121 +1,505 @@
 //! Color palette logic for fractal rendering.
-//! Includes built-in palettes and user-defined gradient support.
-use crate::types::Palette;
+//! Includes built-in palettes, user-defined gradient support, and readers/writers
+//! for Fractint `.map`, GIMP `.ggr` and UltraFractal `.ugr` palette files.
+use crate::types::{Palette, Gradient, ColorStop, RepeatMode, ColorSpace, NamedGradient, PaletteFormat, TransferFunction, TransferParams};
+use crate::colorspace::{mix, to_bytes, from_bytes};
 
-/// Returns the RGB color for a given palette and iteration value.
+/// Interpolate the raw color stops at `position` (0.0 to 1.0), ignoring density and offset.
 ///
-/// * `i` - The iteration count for the pixel (0..=255)
+/// Neighbouring stops are blended in the color space `space`.
+/// Positions before the first stop or after the last take the end colors.
+pub fn interpolate_stops(stops: &[ColorStop], position: f64, space: ColorSpace) -> [f32; 3] {
+	// Nearest stop at or below the position, and nearest stop above it
+	let below = stops
+		.iter()
+		.filter(|s| s.position <= position)
+		.max_by(|a, b| a.position.total_cmp(&b.position));
+	let above = stops
+		.iter()
+		.filter(|s| s.position > position)
+		.min_by(|a, b| a.position.total_cmp(&b.position));
+	let rgb = |c: (u8, u8, u8)| [c.0, c.1, c.2];
+	let float = |c: (u8, u8, u8)| from_bytes(rgb(c));
+	match (below, above) {
+		(Some(a), Some(b)) => {
+			let t = (position - a.position) / (b.position - a.position);
+			mix(rgb(a.color), rgb(b.color), t, space)
+		}
+		(Some(a), None) => float(a.color),
+		(None, Some(b)) => float(b.color),
+		(None, None) => [0.0; 3],
+	}
+}
+
+/// Reshape a palette position (0.0 to 1.0) by a transfer function before the lookup,
+/// spreading out detail that a linear mapping would squeeze into one end of the palette.
+pub fn transfer(t: f64, params: &TransferParams) -> f64 {
+	let t = t.clamp(0.0, 1.0);
+	match params.function {
+		TransferFunction::Linear => t,
+		TransferFunction::Sqrt => t.sqrt(),
+		TransferFunction::CubeRoot => t.cbrt(),
+		TransferFunction::Log => (1.0 + 9.0 * t).log10(),
+		TransferFunction::Power => t.powf(params.exponent.max(1e-3)),
+	}
+}
+
+/// Polynomial fits (coefficients of t^0, t^1, ... per RGB channel, 0.0 to 1.0) of the
+/// matplotlib colormaps, after Matt Zucker's fits of the original 256-entry tables.
+const VIRIDIS: [[f64; 3]; 7] = [
+	[0.2777273272, 0.005407344545, 0.3340998053],
+	[0.1050930431, 1.40461353, 1.384590163],
+	[-0.3308618287, 0.2148475595, 0.09509516303],
+	[-4.634230499, -5.799100973, -19.33244096],
+	[6.228269936, 14.17993337, 56.6905526],
+	[4.776384998, -13.74514538, -65.35303263],
+	[-5.435455856, 4.645852612, 26.31243525],
+];
+const MAGMA: [[f64; 3]; 7] = [
+	[-0.002136485054, -0.0007496550528, -0.005386127855],
+	[0.2516605407, 0.6775232437, 2.494026599],
+	[8.353717279, -3.577719515, 0.314467903],
+	[-27.66873309, 14.26473078, -13.64921319],
+	[52.17613981, -27.94360607, 12.94416944],
+	[-50.76852536, 29.04658282, 4.234152994],
+	[18.65570507, -11.48977352, -5.601961509],
+];
+const INFERNO: [[f64; 3]; 7] = [
+	[0.0002189403691, 0.001651004631, -0.01948089844],
+	[0.1065134195, 0.5639564368, 3.932712389],
+	[11.60249308, -3.972853966, -15.94239411],
+	[-41.70399613, 17.43639888, 44.3541452],
+	[77.1629357, -33.40235894, -81.80730926],
+	[-71.31942824, 32.62606426, 73.20951986],
+	[25.13112622, -12.24266895, -23.070325],
+];
+const PLASMA: [[f64; 3]; 7] = [
+	[0.05873234392, 0.02333670893, 0.5433401827],
+	[2.176514634, 0.2383834171, 0.75396046],
+	[-2.689460476, -7.455851136, 3.11079994],
+	[6.130348346, 42.34618815, -28.51885465],
+	[-11.10743619, -82.66631109, 60.13984767],
+	[10.02306558, 71.4136177, -54.07218656],
+	[-3.658713843, -22.93153465, 18.19190779],
+];
+
+/// Polynomial approximation of Google's Turbo colormap (Anton Mikhailov).
+const TURBO: [[f64; 3]; 6] = [
+	[0.13572138, 0.09140261, 0.10667330],
+	[4.61539260, 2.19418839, 12.64194608],
+	[-42.66032258, 4.84296658, -60.58204836],
+	[132.13108234, -14.18503333, 110.36276771],
+	[-152.94239396, 4.27729857, -89.90310912],
+	[59.28637943, 2.82956604, 27.34824973],
+];
+
+/// Cividis (Nuñez et al.), colorblind-safe blue to yellow, at 64 evenly spaced positions.
+/// Interpolated with a monotone cubic through the published ten-color samples, so the
+/// linear lookup between entries stays smooth.
+const CIVIDIS: [[f64; 3]; 64] = [
+	[0.0000, 0.1255, 0.3020],
+	[0.0000, 0.1361, 0.3233],
+	[0.0000, 0.1466, 0.3478],
+	[0.0000, 0.1570, 0.3731],
+	[0.0000, 0.1675, 0.3968],
+	[0.0000, 0.1781, 0.4166],
+	[0.0000, 0.1889, 0.4302],
+	[0.0000, 0.2000, 0.4353],
+	[0.0094, 0.2114, 0.4344],
+	[0.0344, 0.2231, 0.4322],
+	[0.0701, 0.2350, 0.4291],
+	[0.1117, 0.2469, 0.4258],
+	[0.1543, 0.2589, 0.4227],
+	[0.1932, 0.2707, 0.4205],
+	[0.2235, 0.2824, 0.4196],
+	[0.2461, 0.2937, 0.4198],
+	[0.2654, 0.3049, 0.4202],
+	[0.2824, 0.3160, 0.4210],
+	[0.2977, 0.3271, 0.4222],
+	[0.3121, 0.3382, 0.4236],
+	[0.3263, 0.3494, 0.4254],
+	[0.3412, 0.3608, 0.4275],
+	[0.3562, 0.3723, 0.4300],
+	[0.3705, 0.3839, 0.4330],
+	[0.3844, 0.3956, 0.4364],
+	[0.3980, 0.4074, 0.4401],
+	[0.4115, 0.4192, 0.4438],
+	[0.4252, 0.4311, 0.4475],
+	[0.4392, 0.4431, 0.4510],
+	[0.4535, 0.4553, 0.4548],
+	[0.4678, 0.4675, 0.4591],
+	[0.4822, 0.4798, 0.4635],
+	[0.4967, 0.4922, 0.4677],
+	[0.5114, 0.5046, 0.4712],
+	[0.5262, 0.5171, 0.4736],
+	[0.5412, 0.5294, 0.4745],
+	[0.5564, 0.5417, 0.4741],
+	[0.5717, 0.5538, 0.4729],
+	[0.5873, 0.5660, 0.4710],
+	[0.6030, 0.5782, 0.4686],
+	[0.6188, 0.5905, 0.4656],
+	[0.6348, 0.6029, 0.4624],
+	[0.6510, 0.6157, 0.4588],
+	[0.6673, 0.6287, 0.4551],
+	[0.6838, 0.6418, 0.4511],
+	[0.7004, 0.6551, 0.4467],
+	[0.7172, 0.6685, 0.4419],
+	[0.7342, 0.6821, 0.4365],
+	[0.7513, 0.6959, 0.4304],
+	[0.7686, 0.7098, 0.4235],
+	[0.7863, 0.7239, 0.4158],
+	[0.8045, 0.7382, 0.4072],
+	[0.8229, 0.7527, 0.3980],
+	[0.8413, 0.7674, 0.3882],
+	[0.8595, 0.7821, 0.3780],
+	[0.8772, 0.7969, 0.3675],
+	[0.8941, 0.8118, 0.3569],
+	[0.9103, 0.8267, 0.3459],
+	[0.9258, 0.8417, 0.3345],
+	[0.9409, 0.8569, 0.3227],
+	[0.9557, 0.8721, 0.3106],
+	[0.9703, 0.8873, 0.2985],
+	[0.9850, 0.9025, 0.2864],
+	[1.0000, 0.9176, 0.2745],
+];
+
+/// Look up a dense colormap table at `t` (0.0 to 1.0), blending neighbouring entries.
+fn table_colormap(table: &[[f64; 3]], t: f64) -> [f64; 3] {
+	let x = t.clamp(0.0, 1.0) * (table.len() - 1) as f64;
+	let i = (x as usize).min(table.len() - 2);
+	let f = x - i as f64;
+	[0, 1, 2].map(|k| table[i][k] + (table[i + 1][k] - table[i][k]) * f)
+}
+
+/// Evaluate a polynomial colormap at `t` (0.0 to 1.0).
+fn polynomial_colormap(coefficients: &[[f64; 3]], t: f64) -> [f64; 3] {
+	[0, 1, 2].map(|k| coefficients.iter().rev().fold(0.0, |acc, c| acc * t + c[k]))
+}
+
+/// Look up a gradient at `t` (0.0 to 1.0 across the iteration range), applying
+/// the gradient's density, offset and repeat mode.
+pub fn gradient_color(gradient: &Gradient, t: f64) -> [f32; 3] {
+	let u = t * gradient.density + gradient.offset;
+	let position = match gradient.repeat {
+		RepeatMode::Repeat => u.rem_euclid(1.0),
+		RepeatMode::Mirror => {
+			let m = u.rem_euclid(2.0);
+			if m > 1.0 { 2.0 - m } else { m }
+		}
+		RepeatMode::Clamp => u.clamp(0.0, 1.0),
+	};
+	interpolate_stops(&gradient.stops, position, gradient.interpolation)
+}
+
+/// Returns the RGB color for a given palette at a position along it.
+///
+/// * `t` - Palette position, 0.0 to 1.0 across the iteration range (clamped)
 /// * `palette` - The selected palette enum
-/// * `user_palette` - The two RGB colors for the user-defined gradient
+/// * `gradient` - The multi-stop gradient used by the user-defined palette
 ///
-/// Returns [r, g, b] for the pixel color.
-pub fn palette_color(i: u32, palette: Palette, user_palette: &[(u8, u8, u8); 2]) -> [u8; 3] {
-	// Each palette maps the iteration count to a color.
-	// UserDefined uses a linear gradient between two user-chosen colors.
-	match palette {
-		Palette::Classic => {
-			if i < 255 {
-				[i as u8, 0, 255 - i as u8]
-			} else {
-				[0, 0, 0]
-			}
-		}
-		Palette::Fire => {
-			if i < 255 {
-				[255, (i as f32 * 0.7) as u8, (i as f32 * 0.1) as u8]
-			} else {
-				[0, 0, 0]
-			}
+/// Interior points are colored by the coloring pipeline, not here.
+/// Returns [r, g, b] for the pixel color, each channel 0.0 to 1.0.
+pub fn palette_color(t: f64, palette: Palette, gradient: &Gradient) -> [f32; 3] {
+	// Each built-in palette maps the position (as v = 0..255) to a color.
+	// UserDefined looks the position up in a multi-stop user gradient.
+	let t = t.clamp(0.0, 1.0);
+	let v = t * 255.0;
+	let rgb = match palette {
+		Palette::Classic => [v, 0.0, 255.0 - v],
+		Palette::Fire => [255.0, v * 0.7, v * 0.1],
+		Palette::Ocean => [0.0, v * 0.5, v * 0.9],
+		Palette::Forest => [v * 0.2, v * 0.8, v * 0.3],
+		Palette::Rainbow => [
+			9.0 * (1.0 - t) * t * t * t * 255.0,
+			15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0,
+			8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0,
+		],
+		Palette::Pastel => [200.0, (200.0 - v).max(0.0), 255.0 - v / 2.0],
+		Palette::Sunset => [255.0 * t, 100.0 * (1.0 - t) + 50.0 * t, 50.0 * (1.0 - t)],
+		Palette::Ice => [180.0 * (1.0 - t) + 200.0 * t, 220.0 * t, 255.0 * t],
+		Palette::Neon => [255.0 * (1.0 - t), 255.0 * t, 255.0 * (1.0 - t) * t],
+		Palette::Grayscale => [v, v, v],
+		Palette::Viridis => polynomial_colormap(&VIRIDIS, t).map(|c| c * 255.0),
+		Palette::Magma => polynomial_colormap(&MAGMA, t).map(|c| c * 255.0),
+		Palette::Inferno => polynomial_colormap(&INFERNO, t).map(|c| c * 255.0),
+		Palette::Plasma => polynomial_colormap(&PLASMA, t).map(|c| c * 255.0),
+		Palette::Turbo => polynomial_colormap(&TURBO, t).map(|c| c * 255.0),
+		Palette::Cividis => table_colormap(&CIVIDIS, t).map(|c| c * 255.0),
+		Palette::UserDefined => return gradient_color(gradient, t),
+	};
+	rgb.map(|c| (c / 255.0).clamp(0.0, 1.0) as f32)
+}
+
+/// Number of color positions in an UltraFractal gradient (indices 0..=399).
+const UGR_POSITIONS: f64 = 400.0;
+
+/// Build a gradient with default density/offset from a list of stops.
+fn gradient_from_stops(stops: Vec<ColorStop>) -> Gradient {
+	Gradient { stops, ..Gradient::default() }
+}
+
+/// Stops sorted by position, for writers that need them in order.
+fn sorted_stops(gradient: &Gradient) -> Vec<ColorStop> {
+	let mut stops = gradient.stops.clone();
+	stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+	stops
+}
+
+/// Parse palette file contents in the given format.
+///
+/// * `text` - File contents
+/// * `default_name` - Name used when the file does not carry one (usually the file stem)
+///
+/// Returns the palettes found (UltraFractal files may hold several), or a message
+/// naming the line and problem for malformed files.
+pub fn parse_palette(text: &str, format: PaletteFormat, default_name: &str) -> Result<Vec<NamedGradient>, String> {
+	match format {
+		PaletteFormat::Map => Ok(vec![NamedGradient { name: default_name.to_string(), gradient: parse_map(text)? }]),
+		PaletteFormat::Ggr => Ok(vec![parse_ggr(text, default_name)?]),
+		PaletteFormat::Ugr => parse_ugr(text, default_name),
+	}
+}
+
+/// Write palettes in the given format. `.map` and `.ggr` hold a single palette,
+/// so only the first one is written for those formats.
+pub fn write_palette(palettes: &[NamedGradient], format: PaletteFormat) -> Result<String, String> {
+	let first = palettes.first().ok_or("No palette to write")?;
+	Ok(match format {
+		PaletteFormat::Map => write_map(&first.gradient),
+		PaletteFormat::Ggr => write_ggr(first),
+		PaletteFormat::Ugr => write_ugr(palettes),
+	})
+}
+
+/// Parse a Fractint `.map` file: one "R G B" triple (0-255) per line.
+/// Anything after the third number on a line is treated as a comment.
+pub fn parse_map(text: &str) -> Result<Gradient, String> {
+	let mut colors = Vec::new();
+	for (n, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() {
+			continue;
 		}
-		Palette::Ocean => {
-			if i < 255 {
-				[0, (i as f32 * 0.5) as u8, (i as f32 * 0.9) as u8]
-			} else {
-				[0, 0, 0]
-			}
+		let values: Vec<&str> = line.split_whitespace().take(3).collect();
+		if values.len() < 3 {
+			return Err(format!("line {}: expected three color values \"R G B\", found \"{}\"", n + 1, line));
 		}
-		Palette::Forest => {
-			if i < 255 {
-				[(i as f32 * 0.2) as u8, (i as f32 * 0.8) as u8, (i as f32 * 0.3) as u8]
-			} else {
-				[0, 0, 0]
-			}
+		let mut rgb = [0u8; 3];
+		for (k, v) in values.iter().enumerate() {
+			rgb[k] = v
+				.parse::<u8>()
+				.map_err(|_| format!("line {}: \"{}\" is not a color value between 0 and 255", n + 1, v))?;
 		}
-		Palette::Rainbow => {
-			if i < 255 {
-				let t = i as f32 / 255.0;
-				let r = (9.0 * (1.0 - t) * t * t * t * 255.0) as u8;
-				let g = (15.0 * (1.0 - t) * (1.0 - t) * t * t * 255.0) as u8;
-				let b = (8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t * 255.0) as u8;
-				[r, g, b]
-			} else {
-				[0, 0, 0]
-			}
+		colors.push((rgb[0], rgb[1], rgb[2]));
+	}
+	if colors.len() < 2 {
+		return Err(format!("expected at least 2 colors, found {}", colors.len()));
+	}
+	let last = (colors.len() - 1) as f64;
+	let stops = colors
+		.into_iter()
+		.enumerate()
+		.map(|(i, color)| ColorStop { position: i as f64 / last, color })
+		.collect();
+	Ok(gradient_from_stops(stops))
+}
+
+/// Write a Fractint `.map` file by sampling the gradient stops at 256 positions.
+pub fn write_map(gradient: &Gradient) -> String {
+	(0..256)
+		.map(|i| {
+			let [r, g, b] = to_bytes(interpolate_stops(&gradient.stops, i as f64 / 255.0, gradient.interpolation));
+			format!("{} {} {}\n", r, g, b)
+		})
+		.collect()
+}
+
+/// Convert a float color channel (0.0-1.0) to a byte.
+fn channel_to_u8(v: f64) -> u8 {
+	(v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Parse a GIMP `.ggr` gradient.
+///
+/// Each segment contributes stops at its left and right ends, plus a mid stop when the
+/// midpoint is off-center. Blending curves other than linear and HSV coloring are
+/// approximated by linear RGB blending.
+pub fn parse_ggr(text: &str, default_name: &str) -> Result<NamedGradient, String> {
+	let mut lines = text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+	match lines.next() {
+		Some((_, l)) if l.trim() == "GIMP Gradient" => {}
+		_ => return Err("line 1: missing \"GIMP Gradient\" header".to_string()),
+	}
+	let mut name = default_name.to_string();
+	let (count_line, count_text) = match lines.next() {
+		Some((n, l)) if l.trim_start().starts_with("Name:") => {
+			name = l.trim_start()["Name:".len()..].trim().to_string();
+			lines.next().ok_or(format!("line {}: missing segment count", n + 2))?
 		}
-		Palette::Pastel => {
-			if i < 255 {
-				[200, 200u8.saturating_sub(i as u8), 255u8.saturating_sub(i as u8 / 2)]
-			} else {
-				[0, 0, 0]
-			}
+		Some(entry) => entry,
+		None => return Err("line 2: missing segment count".to_string()),
+	};
+	let count: usize = count_text
+		.trim()
+		.parse()
+		.map_err(|_| format!("line {}: segment count \"{}\" is not a number", count_line + 1, count_text.trim()))?;
+
+	let mut stops = Vec::new();
+	for _ in 0..count {
+		let (n, line) = lines
+			.next()
+			.ok_or(format!("expected {} segments, file ended early", count))?;
+		let values: Vec<f64> = line
+			.split_whitespace()
+			.take(11)
+			.map(|v| v.parse::<f64>().map_err(|_| format!("line {}: \"{}\" is not a number", n + 1, v)))
+			.collect::<Result<_, _>>()?;
+		if values.len() < 11 {
+			return Err(format!("line {}: expected at least 11 values per segment, found {}", n + 1, values.len()));
 		}
-		Palette::Sunset => {
-			if i < 255 {
-				let t = i as f32 / 255.0;
-				[
-					(255.0 * t) as u8,
-					(100.0 * (1.0 - t) + 50.0 * t) as u8,
-					(50.0 * (1.0 - t)) as u8,
-				]
-			} else {
-				[0, 0, 0]
-			}
+		let (left, middle, right) = (values[0], values[1], values[2]);
+		let lc = (channel_to_u8(values[3]), channel_to_u8(values[4]), channel_to_u8(values[5]));
+		let rc = (channel_to_u8(values[7]), channel_to_u8(values[8]), channel_to_u8(values[9]));
+		stops.push(ColorStop { position: left, color: lc });
+		if right > left && ((middle - left) / (right - left) - 0.5).abs() > 1e-3 {
+			// The midpoint is where the blend is half-way between the end colors
+			let mid = ((lc.0 as u16 + rc.0 as u16) / 2, (lc.1 as u16 + rc.1 as u16) / 2, (lc.2 as u16 + rc.2 as u16) / 2);
+			stops.push(ColorStop { position: middle, color: (mid.0 as u8, mid.1 as u8, mid.2 as u8) });
 		}
-		Palette::Ice => {
-			if i < 255 {
-				let t = i as f32 / 255.0;
-				[
-					(180.0 * (1.0 - t) + 200.0 * t) as u8,
-					(220.0 * t) as u8,
-					(255.0 * t) as u8,
-				]
-			} else {
-				[0, 0, 0]
+		stops.push(ColorStop { position: right, color: rc });
+	}
+	if stops.is_empty() {
+		return Err("gradient has no segments".to_string());
+	}
+	Ok(NamedGradient { name, gradient: gradient_from_stops(stops) })
+}
+
+/// Write a GIMP `.ggr` gradient with one linear RGB segment between each pair of stops.
+pub fn write_ggr(palette: &NamedGradient) -> String {
+	let mut stops = sorted_stops(&palette.gradient);
+	// GIMP segments must cover 0..1, so extend the end colors to the edges
+	if let Some(first) = stops.first().copied()
+		&& first.position > 0.0
+	{
+		stops.insert(0, ColorStop { position: 0.0, ..first });
+	}
+	if let Some(last) = stops.last().copied()
+		&& last.position < 1.0
+	{
+		stops.push(ColorStop { position: 1.0, ..last });
+	}
+	let segments: Vec<String> = stops
+		.windows(2)
+		.map(|w| {
+			let (a, b) = (w[0], w[1]);
+			let f = |c: u8| c as f64 / 255.0;
+			format!(
+				"{:.6} {:.6} {:.6} {:.6} {:.6} {:.6} 1.000000 {:.6} {:.6} {:.6} 1.000000 0 0\n",
+				a.position,
+				(a.position + b.position) / 2.0,
+				b.position,
+				f(a.color.0), f(a.color.1), f(a.color.2),
+				f(b.color.0), f(b.color.1), f(b.color.2),
+			)
+		})
+		.collect();
+	format!("GIMP Gradient\nName: {}\n{}\n{}", palette.name, segments.len(), segments.concat())
+}
+
+/// Parse `key=value` pairs from UltraFractal gradient text. Values may be quoted.
+fn ugr_pairs(body: &str) -> Vec<(String, String)> {
+	let mut pairs = Vec::new();
+	let mut rest = body;
+	while let Some(eq) = rest.find('=') {
+		let key = rest[..eq].split_whitespace().last().unwrap_or("").to_string();
+		let after = &rest[eq + 1..];
+		let (value, remaining) = if let Some(quoted) = after.strip_prefix('"') {
+			let end = quoted.find('"').unwrap_or(quoted.len());
+			(&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+		} else {
+			let end = after.find(char::is_whitespace).unwrap_or(after.len());
+			(&after[..end], &after[end..])
+		};
+		pairs.push((key, value.to_string()));
+		rest = remaining;
+	}
+	pairs
+}
+
+/// Parse the body of one UltraFractal gradient entry.
+fn parse_ugr_entry(name: &str, body: &str) -> Result<NamedGradient, String> {
+	// Only the color section matters; opacity stops are ignored
+	let colors = body.split("opacity:").next().unwrap_or(body);
+	let mut title = name.to_string();
+	let mut stops = Vec::new();
+	let mut index = None;
+	for (key, value) in ugr_pairs(colors) {
+		match key.as_str() {
+			"title" => title = value,
+			"index" => {
+				index = Some(value.parse::<f64>().map_err(|_| format!("gradient \"{}\": index \"{}\" is not a number", name, value))?);
 			}
-		}
-		Palette::Neon => {
-			if i < 255 {
-				let t = i as f32 / 255.0;
-				[
-					(255.0 * (1.0 - t)) as u8,
-					(255.0 * t) as u8,
-					(255.0 * (1.0 - t) * t) as u8,
-				]
-			} else {
-				[0, 0, 0]
+			"color" => {
+				let i = index.take().ok_or(format!("gradient \"{}\": color={} has no index before it", name, value))?;
+				let c: u32 = value
+					.parse()
+					.map_err(|_| format!("gradient \"{}\": color \"{}\" is not a number", name, value))?;
+				// UltraFractal stores colors as 0xBBGGRR
+				let color = ((c & 0xff) as u8, ((c >> 8) & 0xff) as u8, ((c >> 16) & 0xff) as u8);
+				// Indices outside 0..=399 wrap around; the same scale as `write_ugr` keeps stops in place
+				let position = i.rem_euclid(UGR_POSITIONS) / (UGR_POSITIONS - 1.0);
+				stops.push(ColorStop { position: position.min(1.0), color });
 			}
+			_ => {}
 		}
-		Palette::Grayscale => {
-			if i < 255 {
-				let v = i as u8;
-				[v, v, v]
-			} else {
-				[0, 0, 0]
-			}
+	}
+	if stops.is_empty() {
+		return Err(format!("gradient \"{}\" has no index=/color= entries", name));
+	}
+	let mut gradient = gradient_from_stops(stops);
+	// UltraFractal gradients wrap around
+	gradient.repeat = RepeatMode::Repeat;
+	Ok(NamedGradient { name: title, gradient })
+}
+
+/// Parse an UltraFractal `.ugr` collection (`Name { gradient: ... }` entries) or a
+/// single `.gradient` file.
+pub fn parse_ugr(text: &str, default_name: &str) -> Result<Vec<NamedGradient>, String> {
+	let mut palettes = Vec::new();
+	let mut rest = text;
+	while let Some(open) = rest.find('{') {
+		let name = rest[..open].lines().last().unwrap_or("").trim();
+		let close = rest[open..]
+			.find('}')
+			.ok_or(format!("gradient \"{}\": missing closing brace", name))?;
+		let name = if name.is_empty() { default_name } else { name };
+		palettes.push(parse_ugr_entry(name, &rest[open + 1..open + close])?);
+		rest = &rest[open + close + 1..];
+	}
+	if palettes.is_empty() {
+		if text.contains("index=") {
+			// A bare gradient body without the surrounding braces
+			palettes.push(parse_ugr_entry(default_name, text)?);
+		} else {
+			return Err("no gradients found (expected \"Name { gradient: ... }\" entries)".to_string());
 		}
-		Palette::UserDefined => {
-			let (r1, g1, b1) = user_palette[0];
-			let (r2, g2, b2) = user_palette[1];
-			if i < 255 {
-				let t = i as f32 / 255.0;
-				[
-					(r1 as f32 * (1.0 - t) + r2 as f32 * t) as u8,
-					(g1 as f32 * (1.0 - t) + g2 as f32 * t) as u8,
-					(b1 as f32 * (1.0 - t) + b2 as f32 * t) as u8,
-				]
-			} else {
-				[0, 0, 0]
-			}
+	}
+	Ok(palettes)
+}
+
+/// Write an UltraFractal `.ugr` collection holding all the given palettes.
+pub fn write_ugr(palettes: &[NamedGradient]) -> String {
+	let mut out = String::new();
+	for palette in palettes {
+		// Braces and quotes would break the entry structure
+		let name: String = palette.name.chars().filter(|c| !matches!(c, '{' | '}' | '"')).collect();
+		out.push_str(&format!("{} {{\ngradient:\n  title=\"{}\" smooth=yes\n", name.replace(' ', "_"), name));
+		for stop in sorted_stops(&palette.gradient) {
+			let index = (stop.position * (UGR_POSITIONS - 1.0)).round() as u32;
+			let color = stop.color.0 as u32 | (stop.color.1 as u32) << 8 | (stop.color.2 as u32) << 16;
+			out.push_str(&format!("  index={} color={}\n", index, color));
 		}
+		out.push_str("opacity:\n  smooth=no index=0 opacity=255\n}\n\n");
 	}
+	out
 }
@@ -1,42 +1,55 @@
 //! Image saving and favorite export/import logic for the fractal visualizer.
-//! Handles PNG output and JSON serialization of favorite views.
+//! Handles PNG output, JSON serialization of favorite views, and palette files.
 
-use crate::types::{ViewRect, Palette, FavoriteSetting, FractalType};
-use crate::fractal::{render_mandelbrot, render_julia};
+use crate::types::{ViewRect, Palette, FavoriteSetting, FractalType, NoiseParams, RenderSettings, NamedGradient, PaletteFormat, ExportFormat};
+use crate::palette::{parse_palette, write_palette};
+use crate::fractal::render_bands;
+use crate::coloring::{to_rgba8, to_rgba16, strip_alpha};
+use crate::noise::noise_field;
 use chrono::Local;
 
-/// Save a PNG of the current fractal view in the `0_fractals/` directory with a unique filename.
+/// Directory that all images and favorites are written to.
+const OUTPUT_DIR: &str = "0_fractals";
+
+/// Create the output directory if it does not exist yet.
+fn ensure_output_dir() -> Result<(), String> {
+	if !std::path::Path::new(OUTPUT_DIR).exists() {
+		std::fs::create_dir_all(OUTPUT_DIR).map_err(|e| format!("Failed to create directory: {e}"))?;
+	}
+	Ok(())
+}
+
+/// Save an image of the current fractal view in the `0_fractals/` directory with a unique filename.
 ///
 /// # Arguments
 /// * `width`, `height` - Output image size in pixels
-/// * `view` - Complex plane region to render
-/// * `palette` - Color palette
-/// * `user_palette` - User-defined gradient colors
-/// * `fractal_type` - Mandelbrot or Julia
-/// * `julia_param` - Julia set parameter (ignored for Mandelbrot)
+/// * `settings` - View, palette and fractal parameters to render
 /// * `high_res` - If true, filename includes 'highres'
+/// * `format` - 8-bit PNG, or 16-bit PNG/TIFF quantized straight from the float colors
+///
+/// The image gets an alpha channel when the coloring leaves any points transparent.
 ///
 /// Returns Ok(path) if successful, or Err(message) on failure.
 pub fn save_fractal_serialized(
 	width: usize,
 	height: usize,
-	view: ViewRect,
-	palette: Palette,
-	user_palette: &[(u8, u8, u8); 2],
-	fractal_type: FractalType,
-	julia_param: (f64, f64),
+	settings: &RenderSettings,
 	high_res: bool,
+	format: ExportFormat,
 ) -> Result<String, String> {
-	// Ensure the output directory exists
-	let dir = "0_fractals";
-	if !std::path::Path::new(dir).exists() {
-		std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {e}"))?;
-	}
+	ensure_output_dir()?;
 
 	// Generate a unique filename with timestamp
 	let now = Local::now();
 	let ts = now.format("%Y%m%d_%H%M%S");
-	let palette_name = match palette {
+	let kind = match settings.fractal_type {
+		FractalType::Mandelbrot | FractalType::Julia => "mandelbrot",
+		FractalType::Noise => "noise",
+		FractalType::Apollonian => "apollonian",
+		FractalType::Kleinian => "kleinian",
+		FractalType::Hybrid => "hybrid",
+	};
+	let palette_name = match settings.palette {
 		Palette::Classic => "classic",
 		Palette::Fire => "fire",
 		Palette::Ocean => "ocean",
@@ -47,17 +60,75 @@ pub fn save_fractal_serialized(
 		Palette::Ice => "ice",
 		Palette::Neon => "neon",
 		Palette::Grayscale => "grayscale",
+		Palette::Viridis => "viridis",
+		Palette::Magma => "magma",
+		Palette::Inferno => "inferno",
+		Palette::Plasma => "plasma",
+		Palette::Cividis => "cividis",
+		Palette::Turbo => "turbo",
 		Palette::UserDefined => "userdefined",
 	};
 	let res = if high_res { "highres" } else { "std" };
-	let filename = format!("{}/mandelbrot_{}_{}_{}x{}_{}.png", dir, palette_name, ts, width, height, res);
+	let filename = format!("{}/{}_{}_{}_{}x{}_{}.{}", OUTPUT_DIR, kind, palette_name, ts, width, height, res, format.extension());
 
-	// Render and save
-	let pixels = match fractal_type {
-		FractalType::Mandelbrot => render_mandelbrot(width, height, view, palette, user_palette),
-		FractalType::Julia => render_julia(width, height, view, palette, user_palette, julia_param),
+	// Render band by band, quantizing each band straight into the output buffer
+	let (w, h) = (width as u32, height as u32);
+	let alpha = settings.coloring.transparency.enabled();
+	let channels = if alpha { 4 } else { 3 };
+	let saved = if format == ExportFormat::Png8 {
+		let mut buffer = Vec::with_capacity(width * height * channels);
+		render_bands(width, height, settings, |band| {
+			let rgba = to_rgba8(band);
+			buffer.extend(if alpha { rgba } else { strip_alpha(&rgba) });
+		});
+		if alpha {
+			image::RgbaImage::from_raw(w, h, buffer).map(|b| b.save(&filename))
+		} else {
+			image::RgbImage::from_raw(w, h, buffer).map(|b| b.save(&filename))
+		}
+	} else {
+		let mut buffer = Vec::with_capacity(width * height * channels);
+		render_bands(width, height, settings, |band| {
+			let rgba = to_rgba16(band);
+			buffer.extend(if alpha { rgba } else { strip_alpha(&rgba) });
+		});
+		if alpha {
+			image::ImageBuffer::<image::Rgba<u16>, _>::from_raw(w, h, buffer).map(|b| b.save(&filename))
+		} else {
+			image::ImageBuffer::<image::Rgb<u16>, _>::from_raw(w, h, buffer).map(|b| b.save(&filename))
+		}
 	};
-	let buffer = image::RgbImage::from_raw(width as u32, height as u32, pixels)
+	saved.ok_or("Failed to create image buffer")?.map_err(|e| e.to_string())?;
+	Ok(filename)
+}
+
+/// Save a noise field as a 16-bit grayscale PNG heightmap in `0_fractals/`.
+///
+/// # Arguments
+/// * `width`, `height` - Output image size in pixels
+/// * `view` - Region of the plane to sample
+/// * `noise` - Noise generator settings
+/// * `high_res` - If true, filename includes 'highres'
+///
+/// Returns Ok(path) if successful, or Err(message) on failure.
+pub fn save_heightmap(
+	width: usize,
+	height: usize,
+	view: ViewRect,
+	noise: &NoiseParams,
+	high_res: bool,
+) -> Result<String, String> {
+	ensure_output_dir()?;
+	let now = Local::now();
+	let ts = now.format("%Y%m%d_%H%M%S");
+	let res = if high_res { "highres" } else { "std" };
+	let filename = format!("{}/heightmap_seed{}_{}_{}x{}_{}.png", OUTPUT_DIR, noise.seed, ts, width, height, res);
+
+	let samples: Vec<u16> = noise_field(width, height, view, noise)
+		.iter()
+		.map(|h| (h * u16::MAX as f64).round() as u16)
+		.collect();
+	let buffer = image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(width as u32, height as u32, samples)
 		.ok_or("Failed to create image buffer")?;
 	buffer.save(&filename).map_err(|e| e.to_string())?;
 	Ok(filename)
@@ -67,13 +138,10 @@ pub fn save_fractal_serialized(
 /// The file can be imported later to restore the view and palette.
 pub fn export_favorite(fav: &FavoriteSetting) -> Result<String, String> {
 	let json = serde_json::to_string_pretty(fav).map_err(|e| e.to_string())?;
-	let dir = "0_fractals";
-	if !std::path::Path::new(dir).exists() {
-		std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {e}"))?;
-	}
+	ensure_output_dir()?;
 	let now = Local::now();
 	let ts = now.format("%Y%m%d_%H%M%S");
-	let filename = format!("{}/favorite_{}.json", dir, ts);
+	let filename = format!("{}/favorite_{}.json", OUTPUT_DIR, ts);
 	std::fs::write(&filename, json).map_err(|e| e.to_string())?;
 	Ok(filename)
 }
@@ -85,3 +153,66 @@ pub fn import_favorite(path: &str) -> Result<FavoriteSetting, String> {
 	let fav: FavoriteSetting = serde_json::from_str(&data).map_err(|e| e.to_string())?;
 	Ok(fav)
 }
+
+/// Import palettes from a `.map`, `.ggr`, `.ugr` or `.gradient` file.
+/// The format is chosen from the file extension.
+pub fn import_palettes(path: &str) -> Result<Vec<NamedGradient>, String> {
+	let path_ref = std::path::Path::new(path);
+	let ext = path_ref.extension().and_then(|e| e.to_str()).unwrap_or("");
+	let format = PaletteFormat::from_extension(ext).ok_or(format!("Unsupported palette file type \".{}\"", ext))?;
+	let name = path_ref.file_stem().and_then(|s| s.to_str()).unwrap_or("Imported");
+	let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+	parse_palette(&text, format, name).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Export palettes to a file of the given format in `0_fractals/`.
+pub fn export_palettes(palettes: &[NamedGradient], format: PaletteFormat) -> Result<String, String> {
+	let text = write_palette(palettes, format)?;
+	ensure_output_dir()?;
+	let now = Local::now();
+	let ts = now.format("%Y%m%d_%H%M%S");
+	let filename = format!("{}/palette_{}.{}", OUTPUT_DIR, ts, format.extension());
+	std::fs::write(&filename, text).map_err(|e| e.to_string())?;
+	Ok(filename)
+}
+
+/// Per-user configuration directory of the app, following each platform's convention.
+fn config_dir() -> Option<std::path::PathBuf> {
+	use std::env::var_os;
+	use std::path::PathBuf;
+	let base = if cfg!(target_os = "windows") {
+		var_os("APPDATA").map(PathBuf::from)
+	} else if cfg!(target_os = "macos") {
+		var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+	} else {
+		var_os("XDG_CONFIG_HOME")
+			.map(PathBuf::from)
+			.or_else(|| var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+	};
+	base.map(|b| b.join("fractal_maker"))
+}
+
+/// Path of the JSON file holding the user's palette library.
+fn palette_library_path() -> Result<std::path::PathBuf, String> {
+	config_dir().map(|d| d.join("palettes.json")).ok_or("No user configuration directory found".to_string())
+}
+
+/// Load the user's palette library. A missing library file is an empty library.
+pub fn load_palette_library() -> Result<Vec<NamedGradient>, String> {
+	let path = palette_library_path()?;
+	if !path.exists() {
+		return Ok(Vec::new());
+	}
+	let data = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+	serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Write the user's palette library, replacing the previous one.
+pub fn save_palette_library(palettes: &[NamedGradient]) -> Result<(), String> {
+	let path = palette_library_path()?;
+	if let Some(dir) = path.parent() {
+		std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {e}"))?;
+	}
+	let json = serde_json::to_string_pretty(palettes).map_err(|e| e.to_string())?;
+	std::fs::write(&path, json).map_err(|e| e.to_string())
+}
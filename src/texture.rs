@@ -0,0 +1,55 @@
+//! Image textures mapped onto the fractal by per-pixel texture coordinates.
+
+use crate::types::{RepeatMode, TextureImage};
+use crate::colorspace::from_bytes;
+
+/// Load a PNG or JPEG as a texture.
+pub fn load_texture(path: &str) -> Result<TextureImage, String> {
+	let img = image::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?.to_rgb8();
+	if img.width() == 0 || img.height() == 0 {
+		return Err(format!("{} has no pixels", path));
+	}
+	Ok(TextureImage {
+		width: img.width() as usize,
+		height: img.height() as usize,
+		pixels: img.pixels().map(|p| from_bytes(p.0)).collect(),
+	})
+}
+
+/// Wrap a texel index into 0..size.
+fn wrap_index(i: i64, size: usize, wrap: RepeatMode) -> usize {
+	let n = size as i64;
+	let i = match wrap {
+		RepeatMode::Repeat => i.rem_euclid(n),
+		RepeatMode::Mirror => {
+			let m = i.rem_euclid(2 * n);
+			if m >= n { 2 * n - 1 - m } else { m }
+		}
+		RepeatMode::Clamp => i.clamp(0, n - 1),
+	};
+	i as usize
+}
+
+/// Bilinearly sample a texture at texture coordinates (u, v), where 0..1 spans the
+/// image left to right and top to bottom.
+pub fn sample_texture(texture: &TextureImage, u: f64, v: f64, wrap: RepeatMode) -> [f32; 3] {
+	// Texel centers sit at half-integer positions
+	let x = u * texture.width as f64 - 0.5;
+	let y = v * texture.height as f64 - 0.5;
+	if !x.is_finite() || !y.is_finite() {
+		return [0.0; 3];
+	}
+	let (x0, y0) = (x.floor(), y.floor());
+	let (fx, fy) = ((x - x0) as f32, (y - y0) as f32);
+	let texel = |dx: i64, dy: i64| {
+		let tx = wrap_index(x0 as i64 + dx, texture.width, wrap);
+		let ty = wrap_index(y0 as i64 + dy, texture.height, wrap);
+		texture.pixels[ty * texture.width + tx]
+	};
+	let (a, b, c, d) = (texel(0, 0), texel(1, 0), texel(0, 1), texel(1, 1));
+	std::array::from_fn(|k| {
+		let top = a[k] + (b[k] - a[k]) * fx;
+		let bottom = c[k] + (d[k] - c[k]) * fx;
+		top + (bottom - top) * fy
+	})
+}
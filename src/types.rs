@@ -2,6 +2,7 @@
 //! Includes fractal view rectangle, palette and fractal type enums, and favorite settings for export/import.
 
 use serde::{Serialize, Deserialize};
+use std::sync::Arc;
 
 /// A rectangle in the complex plane representing the visible fractal region.
 /// Used for both Mandelbrot and Julia sets.
@@ -18,7 +19,7 @@ pub struct ViewRect {
 }
 
 /// Color palette options for fractal rendering.
-/// UserDefined allows a custom two-color gradient.
+/// UserDefined uses a custom multi-stop `Gradient`.
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Palette {
 	/// Blue-to-magenta classic palette
@@ -41,15 +42,900 @@ pub enum Palette {
 	Neon,
 	/// Grayscale
 	Grayscale,
-	/// User-defined two-color gradient
+	/// Perceptually uniform dark purple to yellow (matplotlib)
+	Viridis,
+	/// Perceptually uniform black to pale yellow through purple (matplotlib)
+	Magma,
+	/// Perceptually uniform black to pale yellow through red (matplotlib)
+	Inferno,
+	/// Perceptually uniform blue to yellow through magenta (matplotlib)
+	Plasma,
+	/// Colorblind-safe blue to yellow (matplotlib)
+	Cividis,
+	/// Improved rainbow, dark blue to dark red (Google)
+	Turbo,
+	/// User-defined multi-stop gradient
 	UserDefined,
 }
 
-/// Which fractal to render: Mandelbrot or Julia set.
+/// One color stop of a gradient.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+	/// Position along the gradient, 0.0 to 1.0
+	pub position: f64,
+	/// RGB color at this position
+	pub color: (u8, u8, u8),
+}
+
+/// What a gradient does outside its 0..1 range (after density and offset are applied).
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RepeatMode {
+	/// Wrap around to the start
+	#[default]
+	Repeat,
+	/// Run back and forth
+	Mirror,
+	/// Hold the end colors
+	Clamp,
+}
+
+/// Color space in which gradient colors are blended between stops.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ColorSpace {
+	/// Straight blend of the sRGB byte values
+	#[default]
+	Srgb,
+	/// Blend in linear light, then re-encode as sRGB
+	LinearRgb,
+	/// Hue, saturation, value, taking the shorter way around the hue circle
+	Hsv,
+	/// CIE L*a*b*
+	Lab,
+	/// CIE LCh (polar Lab), shortest hue path
+	Lch,
+	/// OKLab perceptual space
+	Oklab,
+	/// OKLCh (polar OKLab), shortest hue path
+	Oklch,
+}
+
+/// Display names for gradient interpolation color spaces.
+pub const COLOR_SPACE_NAMES: &[(&str, ColorSpace)] = &[
+	("sRGB", ColorSpace::Srgb),
+	("Linear RGB", ColorSpace::LinearRgb),
+	("HSV", ColorSpace::Hsv),
+	("Lab", ColorSpace::Lab),
+	("LCh", ColorSpace::Lch),
+	("OKLab", ColorSpace::Oklab),
+	("OKLCh", ColorSpace::Oklch),
+];
+
+/// A gradient with any number of color stops at arbitrary positions.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+	/// Color stops (need not be sorted; lookups find the neighbours of a position)
+	pub stops: Vec<ColorStop>,
+	/// How many times the gradient spans the iteration range
+	pub density: f64,
+	/// Shift along the gradient, in gradient lengths
+	pub offset: f64,
+	/// Behavior outside the 0..1 range
+	pub repeat: RepeatMode,
+	/// Color space used to blend between neighbouring stops
+	#[serde(default)]
+	pub interpolation: ColorSpace,
+}
+
+impl Default for Gradient {
+	/// Cyan to magenta, matching the old two-color user palette.
+	fn default() -> Self {
+		Self {
+			stops: vec![
+				ColorStop { position: 0.0, color: (0, 255, 255) },
+				ColorStop { position: 1.0, color: (255, 0, 255) },
+			],
+			density: 1.0,
+			offset: 0.0,
+			repeat: RepeatMode::Clamp,
+			interpolation: ColorSpace::Srgb,
+		}
+	}
+}
+
+/// Color clustering used to extract a gradient from a reference image.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExtractMethod {
+	/// Split the color box with the widest channel range at its median, repeatedly
+	MedianCut,
+	/// Lloyd's k-means, seeded with the median-cut colors
+	KMeans,
+}
+
+/// Order of the extracted colors along the gradient.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StopOrder {
+	/// Dark to light
+	Luminance,
+	/// Around the hue circle, starting at red
+	Hue,
+}
+
+/// Color-harmony rule picking the hues of a generated palette.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum HarmonyRule {
+	/// Base hue and its opposite
+	Complementary,
+	/// Base hue and its neighbours 30° either side
+	#[default]
+	Analogous,
+	/// Three hues 120° apart
+	Triadic,
+	/// Base hue and the two hues 30° either side of its opposite
+	SplitComplementary,
+	/// Shades of the base hue only
+	Monochrome,
+}
+
+/// Settings of the seeded palette generator, stored in favorites to reproduce a palette.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorParams {
+	/// Harmony rule for the hues
+	pub rule: HarmonyRule,
+	/// Seed of the random choices
+	pub seed: u64,
+	/// Number of color stops
+	pub stops: usize,
+}
+
+impl Default for GeneratorParams {
+	fn default() -> Self {
+		Self { rule: HarmonyRule::Analogous, seed: 1, stops: 5 }
+	}
+}
+
+/// File format and bit depth of saved images.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+	/// 8 bits per channel PNG
+	#[default]
+	Png8,
+	/// 16 bits per channel PNG
+	Png16,
+	/// 16 bits per channel TIFF
+	Tiff16,
+}
+
+impl ExportFormat {
+	/// File extension of the format.
+	pub fn extension(self) -> &'static str {
+		match self {
+			ExportFormat::Png8 | ExportFormat::Png16 => "png",
+			ExportFormat::Tiff16 => "tif",
+		}
+	}
+}
+
+/// A gradient with a display name, as listed in the palette dropdown.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedGradient {
+	/// Name shown in the UI
+	pub name: String,
+	/// The gradient itself
+	pub gradient: Gradient,
+}
+
+/// Palette file formats that can be imported and exported.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+	/// Fractint `.map`: 256 lines of "R G B"
+	Map,
+	/// GIMP gradient `.ggr`
+	Ggr,
+	/// UltraFractal gradient collection `.ugr` (also `.gradient`)
+	Ugr,
+}
+
+impl PaletteFormat {
+	/// Pick the format from a file extension (case-insensitive).
+	pub fn from_extension(ext: &str) -> Option<PaletteFormat> {
+		match ext.to_ascii_lowercase().as_str() {
+			"map" => Some(PaletteFormat::Map),
+			"ggr" => Some(PaletteFormat::Ggr),
+			"ugr" | "gradient" => Some(PaletteFormat::Ugr),
+			_ => None,
+		}
+	}
+
+	/// File extension used when exporting.
+	pub fn extension(self) -> &'static str {
+		match self {
+			PaletteFormat::Map => "map",
+			PaletteFormat::Ggr => "ggr",
+			PaletteFormat::Ugr => "ugr",
+		}
+	}
+}
+
+/// Which fractal to render.
 #[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FractalType {
     Mandelbrot,
     Julia,
+    /// Procedural noise / terrain field (see `NoiseParams`)
+    Noise,
+    /// Apollonian gasket from three mutually tangent circles (see `ApollonianParams`)
+    Apollonian,
+    /// Limit set of a two-generator Kleinian group (see `KleinianParams`)
+    Kleinian,
+    /// Escape-time fractal that alternates formulas per iteration (see `HybridParams`)
+    Hybrid,
+}
+
+/// How the Julia set is drawn.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum JuliaMode {
+	/// Classic escape-time coloring
+	#[default]
+	EscapeTime,
+	/// Boundary only, by modified inverse iteration (MIIM)
+	Inverse,
+	/// Escape-time image with the MIIM boundary drawn on top
+	Overlay,
+}
+
+/// Parameters for the modified inverse iteration method (MIIM).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InverseParams {
+	/// A pixel stops spawning preimages once it has been hit this many times
+	pub max_hits: u32,
+	/// Maximum number of backward iterations along any branch
+	pub max_depth: u32,
+}
+
+impl Default for InverseParams {
+	fn default() -> Self {
+		Self { max_hits: 4, max_depth: 60 }
+	}
+}
+
+/// Which procedural noise generator to use in `FractalType::Noise` mode.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoiseKind {
+	/// Fractional Brownian motion (summed gradient noise octaves)
+	Fbm,
+	/// Ridged multifractal (Musgrave), sharp mountain ridges
+	Ridged,
+	/// Diamond-square plasma, tileable midpoint displacement
+	Plasma,
+}
+
+/// Parameters for the procedural noise generators.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct NoiseParams {
+	/// Which generator to use
+	pub kind: NoiseKind,
+	/// Number of octaves (subdivision levels for plasma)
+	pub octaves: u32,
+	/// Frequency multiplier between octaves (ignored by plasma, which always doubles)
+	pub lacunarity: f64,
+	/// Amplitude multiplier between octaves (roughness for plasma)
+	pub gain: f64,
+	/// Random seed; the same seed always gives the same field
+	pub seed: u64,
+}
+
+impl Default for NoiseParams {
+	fn default() -> Self {
+		Self {
+			kind: NoiseKind::Fbm,
+			octaves: 6,
+			lacunarity: 2.0,
+			gain: 0.5,
+			seed: 1,
+		}
+	}
+}
+
+/// Parameters for the Apollonian gasket.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ApollonianParams {
+	/// Radii of the three mutually tangent starting circles (relative; the gasket is scaled to the unit circle)
+	pub radii: [f64; 3],
+}
+
+impl Default for ApollonianParams {
+	fn default() -> Self {
+		Self { radii: [1.0, 1.0, 1.0] }
+	}
+}
+
+/// A Möbius transformation z -> (a*z + b) / (c*z + d), stored as the complex entries [a, b, c, d].
+pub type Mobius = [(f64, f64); 4];
+
+/// Parameters for a Kleinian group limit set (Indra's Pearls style).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct KleinianParams {
+	/// First generator `a` (its inverse `A` is computed)
+	pub a: Mobius,
+	/// Second generator `b` (its inverse `B` is computed)
+	pub b: Mobius,
+	/// Maximum word length explored by the depth-first search
+	pub max_depth: u32,
+}
+
+impl Default for KleinianParams {
+	/// The Apollonian group from Grandma's recipe with traces ta = tb = 2.
+	fn default() -> Self {
+		Self {
+			a: [(1.0, 0.0), (0.0, 0.0), (0.0, -2.0), (1.0, 0.0)],
+			b: [(1.0, -1.0), (1.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+			max_depth: 20,
+		}
+	}
+}
+
+/// Iteration formula used by one step of a hybrid fractal.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Formula {
+	/// z = z^p + c
+	Mandelbrot,
+	/// z = (|Re z| + i|Im z|)^p + c
+	BurningShip,
+	/// z = conj(z)^p + c (Mandelbar)
+	Tricorn,
+	/// z = |Re(z^p)| + i Im(z^p) + c
+	Celtic,
+}
+
+/// One entry in a hybrid formula list.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HybridStep {
+	/// Formula applied during this step
+	pub formula: Formula,
+	/// Exponent p used by the formula
+	pub power: f64,
+	/// How many iterations this step runs before moving to the next one
+	pub iterations: u32,
+}
+
+/// Parameters for a hybrid fractal: an ordered list of formula steps.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct HybridParams {
+	/// Steps in the order they are applied
+	pub steps: Vec<HybridStep>,
+	/// Cycle back to the first step after the last; otherwise the last step runs until escape
+	pub repeat: bool,
+	/// Julia-style hybrid: c is fixed to `julia_param` and z starts at the pixel
+	pub julia: bool,
+}
+
+impl Default for HybridParams {
+	/// "Mandelbrot, Mandelbrot, Burning Ship", repeating.
+	fn default() -> Self {
+		let step = |formula| HybridStep { formula, power: 2.0, iterations: 1 };
+		Self {
+			steps: vec![step(Formula::Mandelbrot), step(Formula::Mandelbrot), step(Formula::BurningShip)],
+			repeat: true,
+			julia: false,
+		}
+	}
+}
+
+/// Cumulative distribution of sample values over the iteration range, used by
+/// histogram coloring.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct Histogram {
+	/// Fraction of exterior samples at or below each bin, evenly spaced over 0..255
+	pub cdf: Vec<f32>,
+}
+
+/// What interior (non-escaping) points are colored by.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum InteriorMode {
+	/// One solid color
+	#[default]
+	Solid,
+	/// Magnitude of the final z
+	Magnitude,
+	/// Angle of the final z
+	Angle,
+	/// Period of the attracting cycle the orbit settles on
+	Period,
+	/// Magnitude of the attracting cycle's multiplier (0 at the centers, 1 at the edges)
+	Multiplier,
+	/// Closest approach of the orbit to the orbit trap
+	OrbitTrap,
+	/// Convergent exponential smoothing: sum of exp(-1/|z_n - z_n-1|) over the orbit
+	ExponentialSmoothing,
+}
+
+/// Interior coloring settings, applied independently from the exterior palette.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InteriorParams {
+	/// What interior points are colored by
+	pub mode: InteriorMode,
+	/// Color for the solid mode, and for points whose cycle was not found
+	pub color: (u8, u8, u8),
+	/// Palette used by the other modes
+	pub palette: Palette,
+}
+
+impl Default for InteriorParams {
+	/// Solid black, matching the original interior.
+	fn default() -> Self {
+		Self { mode: InteriorMode::Solid, color: (0, 0, 0), palette: Palette::Grayscale }
+	}
+}
+
+/// Shape of an orbit trap.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TrapShape {
+	/// A single point at the center
+	#[default]
+	Point,
+	/// A line through the center
+	Line,
+	/// Two perpendicular lines through the center
+	Cross,
+	/// A circle of radius `size` around the center
+	Circle,
+	/// An annulus of radius `size` and thickness `width`
+	Ring,
+	/// Grid lines `size` apart, through the center
+	Grid,
+}
+
+/// An orbit trap: the orbit's closest approach to this shape is recorded while iterating.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrapParams {
+	/// Shape of the trap
+	pub shape: TrapShape,
+	/// Center of the trap in the complex plane (re, im)
+	pub center: (f64, f64),
+	/// Radius (circle, ring) or spacing (grid); also the distance over which the palette spans
+	pub size: f64,
+	/// Rotation of lines, crosses and grids in degrees
+	pub angle: f64,
+	/// Thickness of the ring
+	pub width: f64,
+}
+
+impl Default for TrapParams {
+	/// A point trap at the origin.
+	fn default() -> Self {
+		Self { shape: TrapShape::Point, center: (0.0, 0.0), size: 0.5, angle: 0.0, width: 0.1 }
+	}
+}
+
+/// Settings for the average-based coloring algorithms (stripe, triangle inequality, curvature).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AverageParams {
+	/// Number of stripes per turn around the origin (stripe average only)
+	pub stripe_density: f64,
+	/// Weight of the smooth iteration count mixed into the average (0 = average only)
+	pub blend: f64,
+}
+
+impl Default for AverageParams {
+	fn default() -> Self {
+		Self { stripe_density: 5.0, blend: 0.0 }
+	}
+}
+
+/// How the exterior distance estimate is used when coloring.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum DistanceMode {
+	/// Distance estimate not used
+	#[default]
+	Off,
+	/// Draw the boundary as lines of the chosen color
+	Lines,
+	/// Darken the palette towards the boundary
+	Shading,
+	/// Color points near the boundary as interior, so thin filaments stay visible
+	Fill,
+}
+
+/// Settings for distance-estimation rendering (Mandelbrot and Julia sets).
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DistanceParams {
+	/// How the distance estimate is used
+	pub mode: DistanceMode,
+	/// Line thickness (lines, fill) or shading falloff distance, in pixels
+	pub thickness: f64,
+	/// Color of the boundary lines
+	pub color: (u8, u8, u8),
+}
+
+impl Default for DistanceParams {
+	fn default() -> Self {
+		Self { mode: DistanceMode::Off, thickness: 1.0, color: (255, 255, 255) }
+	}
+}
+
+/// Relief lighting: the exterior is treated as a height field lit by a distant light.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightingParams {
+	/// Is lighting applied?
+	pub enabled: bool,
+	/// Direction the light comes from, in degrees counterclockwise from +x
+	pub azimuth: f64,
+	/// Height of the light above the image plane, in degrees
+	pub elevation: f64,
+	/// Light reaching every point
+	pub ambient: f64,
+	/// Strength of the diffuse (Lambert) term
+	pub diffuse: f64,
+	/// Strength of the specular highlight
+	pub specular: f64,
+	/// Sharpness of the specular highlight
+	pub shininess: f64,
+	/// Steepness of the relief
+	pub height: f64,
+}
+
+impl Default for LightingParams {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			azimuth: 45.0,
+			elevation: 45.0,
+			ambient: 0.3,
+			diffuse: 0.7,
+			specular: 0.3,
+			shininess: 20.0,
+			height: 1.5,
+		}
+	}
+}
+
+/// What the palette position of exterior points is taken from.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ColorAlgorithm {
+	/// Iteration count (whole or smooth, optionally histogram-equalized)
+	#[default]
+	Iteration,
+	/// Closest approach of the orbit to the orbit trap
+	TrapDistance,
+	/// Iteration at which the orbit came closest to the orbit trap
+	TrapIteration,
+	/// Average of sin(stripe density × arg z) over the orbit
+	StripeAverage,
+	/// Triangle inequality average: where |z| falls between its bounds at each step
+	TriangleInequality,
+	/// Average turning angle of the orbit
+	CurvatureAverage,
+	/// Sign of Im z at escape: two palette ends
+	BinaryDecomposition,
+	/// Angle of z at escape split into equal sectors
+	AngleSectors,
+	/// Angle of z at escape as a continuous position, showing field lines
+	ExternalAngle,
+	/// Divergent exponential smoothing: sum of exp(-|z|) over the orbit
+	ExponentialSmoothing,
+	/// Convergent exponential smoothing: sum of exp(-1/|z_n - z_n-1|) over the orbit
+	ConvergentSmoothing,
+}
+
+impl ColorAlgorithm {
+	/// Does this algorithm need the orbit trap?
+	pub fn uses_trap(self) -> bool {
+		matches!(self, ColorAlgorithm::TrapDistance | ColorAlgorithm::TrapIteration)
+	}
+
+	/// Index of the orbit average this algorithm reads (stripe, triangle inequality, curvature).
+	pub fn average_index(self) -> Option<usize> {
+		match self {
+			ColorAlgorithm::StripeAverage => Some(0),
+			ColorAlgorithm::TriangleInequality => Some(1),
+			ColorAlgorithm::CurvatureAverage => Some(2),
+			_ => None,
+		}
+	}
+
+	/// Index of the exponential smoothing sum this algorithm reads (divergent, convergent).
+	pub fn exponential_index(self) -> Option<usize> {
+		match self {
+			ColorAlgorithm::ExponentialSmoothing => Some(0),
+			ColorAlgorithm::ConvergentSmoothing => Some(1),
+			_ => None,
+		}
+	}
+}
+
+/// Which points are left transparent, so the image can be composited over other artwork.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransparencyParams {
+	/// Make points inside the set transparent
+	pub interior: bool,
+	/// Make exterior points within an iteration range transparent
+	pub range: bool,
+	/// First iteration count of the transparent range
+	pub min: f64,
+	/// Last iteration count of the transparent range
+	pub max: f64,
+}
+
+impl Default for TransparencyParams {
+	fn default() -> Self {
+		Self { interior: false, range: false, min: 0.0, max: 10.0 }
+	}
+}
+
+impl TransparencyParams {
+	/// Can any point end up transparent?
+	pub fn enabled(&self) -> bool {
+		self.interior || self.range
+	}
+}
+
+/// A texture image in linear memory, loaded once and shared between renders.
+pub struct TextureImage {
+	/// Width in pixels
+	pub width: usize,
+	/// Height in pixels
+	pub height: usize,
+	/// RGB colors 0.0 to 1.0, row by row from the top
+	pub pixels: Vec<[f32; 3]>,
+}
+
+/// Which coordinates look up the texture image.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TextureSource {
+	/// Final z: its argument and the fractional iteration count outside the set,
+	/// its real and imaginary parts inside
+	#[default]
+	FinalZ,
+	/// Offset from the orbit trap center at the orbit's closest approach, in the trap's frame
+	OrbitTrap,
+}
+
+/// An image mapped onto the fractal by the final z or orbit trap coordinates.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextureParams {
+	/// Draw the texture
+	pub enabled: bool,
+	/// Image file the texture is loaded from
+	pub path: String,
+	/// Coordinates used as texture coordinates
+	pub source: TextureSource,
+	/// What the texture does outside its 0..1 coordinate range
+	pub wrap: RepeatMode,
+	/// Texture repeats per unit of the coordinates
+	pub scale: f64,
+	/// Shift of the texture coordinates, in texture widths and heights
+	pub offset: (f64, f64),
+	/// Also texture points inside the set
+	pub interior: bool,
+	/// How the texture is composited over the colors
+	pub blend: BlendMode,
+	/// Strength of the texture, 0.0 to 1.0
+	pub opacity: f64,
+}
+
+impl Default for TextureParams {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			path: String::new(),
+			source: TextureSource::FinalZ,
+			wrap: RepeatMode::Repeat,
+			scale: 1.0,
+			offset: (0.0, 0.0),
+			interior: true,
+			blend: BlendMode::Normal,
+			opacity: 1.0,
+		}
+	}
+}
+
+/// Settings of the angle decomposition coloring.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecompositionParams {
+	/// Number of equal angle sectors (n-ary decomposition)
+	pub sectors: u32,
+}
+
+impl Default for DecompositionParams {
+	fn default() -> Self {
+		Self { sectors: 4 }
+	}
+}
+
+/// Curve applied to a palette position before the color lookup.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum TransferFunction {
+	/// Position used as is
+	#[default]
+	Linear,
+	/// Square root: spreads out the low end
+	Sqrt,
+	/// Cube root: spreads out the low end even more
+	CubeRoot,
+	/// Logarithmic
+	Log,
+	/// Power of the position with a chosen exponent
+	Power,
+}
+
+/// Transfer function with its parameter.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransferParams {
+	/// Curve applied to the position
+	pub function: TransferFunction,
+	/// Exponent of the power curve (only used by `Power`)
+	pub exponent: f64,
+}
+
+impl Default for TransferParams {
+	fn default() -> Self {
+		Self { function: TransferFunction::Linear, exponent: 2.0 }
+	}
+}
+
+/// How a color layer is combined with the colors beneath it.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum BlendMode {
+	/// Layer color replaces the one beneath
+	#[default]
+	Normal,
+	/// Product of both colors (always darker)
+	Multiply,
+	/// Inverse product of the inverted colors (always lighter)
+	Screen,
+	/// Multiply in the shadows, screen in the highlights of the color beneath
+	Overlay,
+	/// Gentle darkening or lightening by the layer color
+	SoftLight,
+	/// Hue of the layer with the saturation and luminosity beneath
+	Hue,
+}
+
+/// An extra coloring of the exterior composited over the base coloring.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorLayer {
+	/// Is the layer drawn?
+	pub enabled: bool,
+	/// What the layer's palette position is taken from
+	pub algorithm: ColorAlgorithm,
+	/// Built-in palette of the layer (ignored if `custom` is set)
+	pub palette: Palette,
+	/// Custom palette of the layer, copied from the palette list
+	pub custom: Option<NamedGradient>,
+	/// Curve applied to the position before the palette lookup
+	pub transfer: TransferParams,
+	/// How the layer is combined with the colors beneath
+	pub blend: BlendMode,
+	/// Strength of the layer (0.0 to 1.0)
+	pub opacity: f64,
+}
+
+impl Default for ColorLayer {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			algorithm: ColorAlgorithm::StripeAverage,
+			palette: Palette::Grayscale,
+			custom: None,
+			transfer: TransferParams::default(),
+			blend: BlendMode::Overlay,
+			opacity: 0.5,
+		}
+	}
+}
+
+/// How iteration data is mapped onto the palette.
+#[derive(Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct ColoringParams {
+	/// What exterior points are colored by
+	#[serde(default)]
+	pub algorithm: ColorAlgorithm,
+	/// Use the continuous (smooth) iteration count instead of whole iterations
+	pub smooth: bool,
+	/// Spread the palette by the cumulative distribution of the frame's values
+	pub histogram: bool,
+	/// Histogram kept from an earlier frame and used instead of the current one
+	pub frozen_histogram: Option<Histogram>,
+	/// Sector count of the angle decomposition coloring
+	#[serde(default)]
+	pub decomposition: DecompositionParams,
+	/// Curve applied to the exterior position before the palette lookup
+	#[serde(default)]
+	pub transfer: TransferParams,
+	/// Shift along the palette, in palette lengths (wraps around; animated by color cycling)
+	#[serde(default)]
+	pub offset: f64,
+	/// How points inside the set are colored
+	#[serde(default)]
+	pub interior: InteriorParams,
+	/// Boundary lines, shading or fill from the exterior distance estimate
+	#[serde(default)]
+	pub distance: DistanceParams,
+	/// Relief lighting multiplied onto the exterior colors
+	#[serde(default)]
+	pub lighting: LightingParams,
+	/// Color layers composited over the exterior, bottom to top
+	#[serde(default)]
+	pub layers: Vec<ColorLayer>,
+	/// Points left transparent
+	#[serde(default)]
+	pub transparency: TransparencyParams,
+	/// Image mapped onto the fractal
+	#[serde(default)]
+	pub texture: TextureParams,
+}
+
+impl ColoringParams {
+	/// Is the palette spread by a histogram of the values?
+	pub fn uses_histogram(&self) -> bool {
+		self.histogram && self.algorithm == ColorAlgorithm::Iteration
+	}
+
+	/// The exterior algorithm and those of the enabled layers.
+	fn algorithms(&self) -> impl Iterator<Item = ColorAlgorithm> + '_ {
+		std::iter::once(self.algorithm).chain(self.layers.iter().filter(|l| l.enabled).map(|l| l.algorithm))
+	}
+
+	/// Is the orbit trap used by the coloring?
+	pub fn uses_trap(&self) -> bool {
+		self.algorithms().any(ColorAlgorithm::uses_trap)
+			|| self.interior.mode == InteriorMode::OrbitTrap
+			|| (self.texture.enabled && self.texture.source == TextureSource::OrbitTrap)
+	}
+
+	/// Which orbit averages (stripe, triangle inequality, curvature) the coloring reads.
+	pub fn uses_averages(&self) -> [bool; 3] {
+		let mut used = [false; 3];
+		for k in self.algorithms().filter_map(ColorAlgorithm::average_index) {
+			used[k] = true;
+		}
+		used
+	}
+
+	/// Which exponential smoothing sums (divergent, convergent) the coloring reads.
+	pub fn uses_exponential(&self) -> [bool; 2] {
+		let mut used = [false; 2];
+		for k in self.algorithms().filter_map(ColorAlgorithm::exponential_index) {
+			used[k] = true;
+		}
+		used[1] |= self.interior.mode == InteriorMode::ExponentialSmoothing;
+		used
+	}
+}
+
+/// Everything needed to render one image, shared by the viewer and the PNG export.
+#[derive(Clone)]
+pub struct RenderSettings {
+	/// The visible region in the complex plane
+	pub view: ViewRect,
+	/// The selected color palette
+	pub palette: Palette,
+	/// User-defined gradient (only used if palette == UserDefined)
+	pub gradient: Gradient,
+	/// Which fractal to render
+	pub fractal_type: FractalType,
+	/// Julia set parameter (only used if fractal_type == Julia)
+	pub julia_param: (f64, f64),
+	/// Escape-time, inverse iteration, or both (only used if fractal_type == Julia)
+	pub julia_mode: JuliaMode,
+	/// Inverse iteration settings (only used by the inverse Julia modes)
+	pub inverse: InverseParams,
+	/// Noise generator settings (only used if fractal_type == Noise)
+	pub noise: NoiseParams,
+	/// Gasket settings (only used if fractal_type == Apollonian)
+	pub apollonian: ApollonianParams,
+	/// Kleinian group generators (only used if fractal_type == Kleinian)
+	pub kleinian: KleinianParams,
+	/// Hybrid formula list (only used if fractal_type == Hybrid)
+	pub hybrid: HybridParams,
+	/// How iteration data is mapped onto the palette
+	pub coloring: ColoringParams,
+	/// Orbit trap tracked while iterating (escape-time fractals only)
+	pub trap: TrapParams,
+	/// Stripe density and blend of the average coloring algorithms
+	pub averages: AverageParams,
+	/// Image loaded from `coloring.texture.path` (None if no texture is loaded)
+	pub texture: Option<Arc<TextureImage>>,
 }
 
 /// A favorite fractal view and settings, for export/import as JSON.
@@ -59,10 +945,43 @@ pub struct FavoriteSetting {
 	pub view: ViewRect,
 	/// The selected color palette
 	pub palette: Palette,
-	/// Mandelbrot or Julia
+	/// User-defined gradient (only used if palette == UserDefined)
+	#[serde(default)]
+	pub gradient: Gradient,
+	/// Which fractal to render
 	pub fractal_type: FractalType,
 	/// Julia set parameter (only used if fractal_type == Julia)
 	pub julia_param: (f64, f64),
+	/// Escape-time, inverse iteration, or both (only used if fractal_type == Julia)
+	#[serde(default)]
+	pub julia_mode: JuliaMode,
+	/// Inverse iteration settings (only used by the inverse Julia modes)
+	#[serde(default)]
+	pub inverse: InverseParams,
+	/// Noise generator settings (only used if fractal_type == Noise)
+	#[serde(default)]
+	pub noise: NoiseParams,
+	/// Gasket settings (only used if fractal_type == Apollonian)
+	#[serde(default)]
+	pub apollonian: ApollonianParams,
+	/// Kleinian group generators (only used if fractal_type == Kleinian)
+	#[serde(default)]
+	pub kleinian: KleinianParams,
+	/// Hybrid formula list (only used if fractal_type == Hybrid)
+	#[serde(default)]
+	pub hybrid: HybridParams,
+	/// How iteration data is mapped onto the palette
+	#[serde(default)]
+	pub coloring: ColoringParams,
+	/// Orbit trap tracked while iterating (escape-time fractals only)
+	#[serde(default)]
+	pub trap: TrapParams,
+	/// Stripe density and blend of the average coloring algorithms
+	#[serde(default)]
+	pub averages: AverageParams,
+	/// Palette generator settings the gradient was made with
+	#[serde(default)]
+	pub generator: GeneratorParams,
 }
 
 /// List of built-in palette names and variants for the UI dropdown.
@@ -78,5 +997,80 @@ pub const PALETTE_NAMES: &[(&str, Palette)] = &[
     ("Ice", Palette::Ice),
     ("Neon", Palette::Neon),
     ("Grayscale", Palette::Grayscale),
+    ("Viridis", Palette::Viridis),
+    ("Magma", Palette::Magma),
+    ("Inferno", Palette::Inferno),
+    ("Plasma", Palette::Plasma),
+    ("Cividis", Palette::Cividis),
+    ("Turbo", Palette::Turbo),
     ("User Defined", Palette::UserDefined),
 ];
+
+/// Interior coloring mode names and variants for the UI.
+pub const INTERIOR_MODE_NAMES: &[(&str, InteriorMode)] = &[
+    ("Solid", InteriorMode::Solid),
+    ("Final |z|", InteriorMode::Magnitude),
+    ("Final angle", InteriorMode::Angle),
+    ("Cycle period", InteriorMode::Period),
+    ("Multiplier", InteriorMode::Multiplier),
+    ("Orbit trap", InteriorMode::OrbitTrap),
+    ("Exponential smoothing", InteriorMode::ExponentialSmoothing),
+];
+
+/// Exterior coloring algorithm names and variants for the UI.
+pub const COLOR_ALGORITHM_NAMES: &[(&str, ColorAlgorithm)] = &[
+    ("Iterations", ColorAlgorithm::Iteration),
+    ("Trap distance", ColorAlgorithm::TrapDistance),
+    ("Trap iteration", ColorAlgorithm::TrapIteration),
+    ("Stripe average", ColorAlgorithm::StripeAverage),
+    ("Triangle inequality", ColorAlgorithm::TriangleInequality),
+    ("Curvature average", ColorAlgorithm::CurvatureAverage),
+    ("Binary decomposition", ColorAlgorithm::BinaryDecomposition),
+    ("Angle sectors", ColorAlgorithm::AngleSectors),
+    ("External angle", ColorAlgorithm::ExternalAngle),
+    ("Exponential smoothing", ColorAlgorithm::ExponentialSmoothing),
+    ("Convergent smoothing", ColorAlgorithm::ConvergentSmoothing),
+];
+
+/// Transfer function names and variants for the UI.
+pub const TRANSFER_FUNCTION_NAMES: &[(&str, TransferFunction)] = &[
+    ("Linear", TransferFunction::Linear),
+    ("Square root", TransferFunction::Sqrt),
+    ("Cube root", TransferFunction::CubeRoot),
+    ("Log", TransferFunction::Log),
+    ("Power", TransferFunction::Power),
+];
+
+/// Blend mode names and variants for the layers panel.
+pub const BLEND_MODE_NAMES: &[(&str, BlendMode)] = &[
+    ("Normal", BlendMode::Normal),
+    ("Multiply", BlendMode::Multiply),
+    ("Screen", BlendMode::Screen),
+    ("Overlay", BlendMode::Overlay),
+    ("Soft light", BlendMode::SoftLight),
+    ("Hue", BlendMode::Hue),
+];
+
+/// Harmony rule names and variants for the palette generator.
+pub const HARMONY_RULE_NAMES: &[(&str, HarmonyRule)] = &[
+    ("Complementary", HarmonyRule::Complementary),
+    ("Analogous", HarmonyRule::Analogous),
+    ("Triadic", HarmonyRule::Triadic),
+    ("Split complementary", HarmonyRule::SplitComplementary),
+    ("Monochrome", HarmonyRule::Monochrome),
+];
+
+/// Image export format names and variants for the UI.
+pub const EXPORT_FORMAT_NAMES: &[(&str, ExportFormat)] = &[
+    ("PNG 8-bit", ExportFormat::Png8),
+    ("PNG 16-bit", ExportFormat::Png16),
+    ("TIFF 16-bit", ExportFormat::Tiff16),
+];
+
+/// Formula names and variants for the hybrid step editor.
+pub const FORMULA_NAMES: &[(&str, Formula)] = &[
+    ("Mandelbrot", Formula::Mandelbrot),
+    ("Burning Ship", Formula::BurningShip),
+    ("Tricorn", Formula::Tricorn),
+    ("Celtic", Formula::Celtic),
+];